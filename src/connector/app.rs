@@ -1,8 +1,9 @@
 use ethers::{
     prelude::k256::SecretKey,
     providers::{Http, Provider, Ws},
-    signers::LocalWallet,
-    types::U256,
+    signers::{LocalWallet, Signer},
+    types::{Bytes, H160, H256, U256},
+    utils::format_ether,
 };
 use hex::decode;
 use log::info;
@@ -10,119 +11,1616 @@ use serde::Deserialize;
 use spinners::{Spinner, Spinners};
 use std::{collections::HashMap, fs::File, io::Read, sync::Arc, time::Duration};
 use tokio::time::sleep;
+use url::Url;
 
+use crate::connector::cli::CommonArgs;
+use crate::connector::schema::{
+    deserialize_gwei, deserialize_url, validate_settings, SlippageBps, Wei,
+};
+use crate::connector::wallets::{derive_mnemonic_wallets, keystore_password, load_keystore_wallet};
 use crate::core::{
-    contracts::CustomError, private_txn::uniswap_v2_bundler, public_txn::uniswap_v2_transaction,
+    chain::{detect_chain, ChainConfig, V2Deployment, V3Deployment},
+    contracts::{
+        apply_slippage, load_erc20_contract, load_flashbots_client_middleware,
+        load_uniswap_v2_mempool, parse_url, resolve_sell_amount, BuilderConfig, BundleClient,
+        CustomError, RelayConfig, SellAmount, BUILDER_URLS, FLASHBOTS_RELAY_URL,
+    },
+    fleet::{fund_wallets, sweep_wallets},
+    gas::{GasLimitPolicy, SendSettings},
+    pair::TransferTax,
+    preflight::{check_wallets, print_balances},
+    private_txn::{
+        uniswap_v2_bundler, uniswap_v2_multi_wallet_bundler, uniswap_v2_sell_bundler,
+        uniswap_v3_bundler, uniswap_v3_sell_bundler, BundleTarget,
+    },
+    public_txn::{
+        uniswap_v2_sell_transaction, uniswap_v2_transaction, uniswap_v3_sell_transaction,
+        uniswap_v3_transaction,
+    },
+    router::{find_best_route, quote_buy, V2Trade},
+    safety::{analyze_token, TokenSafetyReport},
+    slippage::{auto_slippage, AutoSlippageLimits, TradeSide},
+    v3::{quote_exact_input, resolve_buy_route, V3RouteConfig, V3Trade, V3_FEE_TIERS},
+    watcher::{wait_for_launch, wait_for_liquidity, LaunchEvent},
 };
 
 #[allow(non_snake_case)]
 #[derive(Debug, Deserialize)]
 pub struct Settings {
+    /// Wallet name to plaintext hex private key.
+    #[serde(default)]
     pub wallets: HashMap<String, String>,
-    pub tokenToBuy: String,
-    pub slippage: f64,
+    /// Wallet name to encrypted JSON keystore path. The password comes from
+    /// `KEYSTORE_PASSWORD` or a terminal prompt.
+    #[serde(default)]
+    pub keystores: HashMap<String, String>,
+    /// Wallets derived from a BIP-39 mnemonic.
+    pub mnemonic: Option<MnemonicSettings>,
+    pub tokenToBuy: H160,
+    pub slippage: SlippageBps,
     pub autoSlippage: bool,
-    pub amountOfETHToBuy: HashMap<String, f64>,
-    pub BuyExtraGas: f64,
-    pub MinerTip: f64,
+    /// Bounds for the tolerance picked by `autoSlippage`.
+    #[serde(default)]
+    pub autoSlippageLimits: AutoSlippageSettings,
+    pub amountOfETHToBuy: HashMap<String, Wei>,
+    /// Gwei added to the max fee.
+    #[serde(deserialize_with = "deserialize_gwei")]
+    pub BuyExtraGas: Wei,
+    /// Priority fee, in gwei.
+    #[serde(deserialize_with = "deserialize_gwei")]
+    pub MinerTip: Wei,
     pub delayBetweenEachWalletBuy: u64,
     pub numberOfRounds: u32,
     pub PrivateTransaction: bool,
+    /// In private mode, pack every wallet's buy for a round into one bundle
+    /// instead of sending one bundle per wallet.
+    #[serde(default)]
+    pub singleBundle: bool,
+    /// Hex private key that authenticates bundles with relays and builders.
+    /// Required when `PrivateTransaction` is true; never used to trade.
+    pub bundleSigner: Option<String>,
+    /// Number of target blocks a bundle is re-submitted for before giving up.
+    #[serde(default = "default_bundle_block_budget")]
+    pub bundleBlockBudget: u64,
     pub rpc: RpcSettings,
+    #[serde(default)]
+    pub sell: SellSettings,
+    #[serde(default)]
+    pub snipe: SnipeSettings,
+    #[serde(default)]
+    pub flashbots: FlashbotsSettings,
+    /// Wallet that funds the fleet and receives sweeps.
+    pub treasury: Option<TreasurySettings>,
+    /// What to do when the pre-flight check finds wallets that cannot cover
+    /// their swap plus gas.
+    #[serde(default)]
+    pub underfundedWallets: UnderfundedPolicy,
+    #[serde(default)]
+    pub safety: SafetySettings,
+    #[serde(default)]
+    pub gas: GasSettings,
+    #[serde(default)]
+    pub uniswapV3: UniswapV3Settings,
+    /// Forces the V2 buy path, from WETH to `tokenToBuy`. Sells use it
+    /// reversed. The best route is searched for when unset.
+    pub route: Option<Vec<H160>>,
+    /// V2 fork to trade on instead of the chain's default DEX.
+    pub dex: Option<DexSettings>,
+    /// Build and sign every swap as usual, but only simulate it (`eth_call`,
+    /// or `simulate_bundle` in private mode) and print the result. Also set
+    /// by `--dry-run`.
+    #[serde(default)]
+    pub dryRun: bool,
+}
+
+impl Settings {
+    /// Checks that the `route` override runs from `weth` to `tokenToBuy`.
+    pub fn route_override(&self, weth: H160) -> eyre::Result<Option<Vec<H160>>> {
+        let route = match &self.route {
+            Some(route) => route,
+            None => return Ok(None),
+        };
+
+        if route.len() < 2 || route[0] != weth || route[route.len() - 1] != self.tokenToBuy {
+            return Err(eyre::eyre!(
+                "route must start with WETH and end with tokenToBuy, got {:?}",
+                route
+            ));
+        }
+
+        Ok(Some(route.clone()))
+    }
+
+    /// Wei `wallet` buys with per round.
+    pub fn amount_for(&self, wallet: &str) -> eyre::Result<U256> {
+        self.amountOfETHToBuy
+            .get(wallet)
+            .map(|amount| amount.0)
+            .ok_or_else(|| eyre::eyre!("No amountOfETHToBuy for wallet {}", wallet))
+    }
+
+    /// `tokenToBuy` as the swap functions take it.
+    pub fn token_address(&self) -> String {
+        format!("{:?}", self.tokenToBuy)
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Default, Deserialize)]
+pub struct UniswapV3Settings {
+    /// Buy and sell through SwapRouter02 instead of the V2 router.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Pool fee tier (100, 500, 3000 or 10000). Picked per hop by liquidity
+    /// when unset.
+    pub feeTier: Option<u32>,
+    /// Intermediate tokens for a multi-hop route, in buy order.
+    #[serde(default)]
+    pub via: Vec<H160>,
+}
+
+impl UniswapV3Settings {
+    /// The route config on `deployment`, with routes starting from `weth`.
+    pub fn route_config(
+        &self,
+        deployment: V3Deployment,
+        weth: H160,
+    ) -> eyre::Result<V3RouteConfig> {
+        if let Some(fee_tier) = self.feeTier {
+            if !V3_FEE_TIERS.contains(&fee_tier) {
+                return Err(eyre::eyre!(
+                    "uniswapV3.feeTier must be one of {:?}, got {}",
+                    V3_FEE_TIERS,
+                    fee_tier
+                ));
+            }
+        }
+
+        Ok(V3RouteConfig {
+            fee_tier: self.feeTier,
+            via: self.via.clone(),
+            deployment,
+            weth,
+        })
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct DexSettings {
+    pub router: H160,
+    pub factory: H160,
+    /// Hash of the fork's pair creation code, used to derive pair addresses.
+    pub initCodeHash: H256,
+    /// Swap fee as a fraction, 0.0025 on PancakeSwap.
+    #[serde(default = "default_dex_fee")]
+    pub fee: f64,
+}
+
+fn default_dex_fee() -> f64 {
+    0.003
+}
+
+impl DexSettings {
+    /// The fee range is checked by `validate_settings`.
+    pub fn deployment(&self) -> V2Deployment {
+        V2Deployment {
+            router: self.router,
+            factory: self.factory,
+            init_code_hash: self.initCodeHash,
+            fee_bps: (self.fee * 10_000.0).round() as u64,
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct AutoSlippageSettings {
+    #[serde(default = "default_min_slippage")]
+    pub minSlippage: f64,
+    #[serde(default = "default_max_slippage")]
+    pub maxSlippage: f64,
+    /// Blocks of pair history used to estimate volatility.
+    #[serde(default = "default_lookback_blocks")]
+    pub lookbackBlocks: u64,
+    /// Milliseconds the mempool feed is watched for pending buys.
+    #[serde(default = "default_mempool_window_ms")]
+    pub mempoolWindowMs: u64,
+}
+
+impl Default for AutoSlippageSettings {
+    fn default() -> Self {
+        AutoSlippageSettings {
+            minSlippage: default_min_slippage(),
+            maxSlippage: default_max_slippage(),
+            lookbackBlocks: default_lookback_blocks(),
+            mempoolWindowMs: default_mempool_window_ms(),
+        }
+    }
+}
+
+impl AutoSlippageSettings {
+    pub fn limits(&self) -> AutoSlippageLimits {
+        AutoSlippageLimits {
+            min_slippage: self.minSlippage,
+            max_slippage: self.maxSlippage,
+            lookback_blocks: self.lookbackBlocks,
+            mempool_window: Duration::from_millis(self.mempoolWindowMs),
+        }
+    }
+}
+
+fn default_min_slippage() -> f64 {
+    0.005
+}
+
+fn default_max_slippage() -> f64 {
+    0.05
+}
+
+fn default_lookback_blocks() -> u64 {
+    50
+}
+
+fn default_mempool_window_ms() -> u64 {
+    500
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GasSettings {
+    /// Safety multiplier applied to estimated or simulated gas usage.
+    #[serde(default = "default_gas_multiplier")]
+    pub multiplier: f64,
+    /// Gas limit no swap may exceed.
+    #[serde(default = "default_gas_cap")]
+    pub cap: u64,
+    /// Gas limit used when estimation reverts.
+    #[serde(default = "default_gas_fallback")]
+    pub fallback: u64,
+}
+
+impl Default for GasSettings {
+    fn default() -> Self {
+        GasSettings {
+            multiplier: default_gas_multiplier(),
+            cap: default_gas_cap(),
+            fallback: default_gas_fallback(),
+        }
+    }
+}
+
+impl GasSettings {
+    pub fn policy(&self) -> GasLimitPolicy {
+        GasLimitPolicy {
+            multiplier: self.multiplier,
+            cap: self.cap,
+            fallback: self.fallback,
+        }
+    }
+}
+
+fn default_gas_multiplier() -> f64 {
+    GasLimitPolicy::default().multiplier
+}
+
+fn default_gas_cap() -> u64 {
+    GasLimitPolicy::default().cap
+}
+
+fn default_gas_fallback() -> u64 {
+    GasLimitPolicy::default().fallback
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct SafetySettings {
+    /// Simulate a buy-then-sell of `tokenToBuy` before buying for real. Off
+    /// by default.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Highest acceptable buy tax, as a fraction.
+    #[serde(default = "default_max_tax")]
+    pub maxBuyTax: f64,
+    /// Highest acceptable sell tax, as a fraction.
+    #[serde(default = "default_max_tax")]
+    pub maxSellTax: f64,
+    /// ETH spent by the simulated buy.
+    #[serde(default = "default_probe_amount")]
+    pub probeAmountOfETH: Wei,
+}
+
+impl Default for SafetySettings {
+    fn default() -> Self {
+        SafetySettings {
+            enabled: false,
+            maxBuyTax: default_max_tax(),
+            maxSellTax: default_max_tax(),
+            probeAmountOfETH: default_probe_amount(),
+        }
+    }
+}
+
+fn default_max_tax() -> f64 {
+    0.1
+}
+
+fn default_probe_amount() -> Wei {
+    Wei(U256::exp10(15))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnderfundedPolicy {
+    /// Refuse to start.
+    #[default]
+    Abort,
+    /// Leave the underfunded wallets out of the run.
+    Skip,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct MnemonicSettings {
+    /// BIP-39 phrase. Falls back to the `WALLET_MNEMONIC` environment variable.
+    pub phrase: Option<String>,
+    /// Base path plus index range, e.g. `m/44'/60'/0'/0/0..49`.
+    pub derivationPath: String,
+    /// Derived wallets are named `<namePrefix><index>`.
+    #[serde(default = "default_mnemonic_name_prefix")]
+    pub namePrefix: String,
+    /// ETH to buy for derived wallets without an `amountOfETHToBuy` entry.
+    pub defaultAmountOfETHToBuy: Option<Wei>,
+}
+
+fn default_mnemonic_name_prefix() -> String {
+    "hd".to_string()
 }
 
 #[allow(non_snake_case)]
 #[derive(Debug, Deserialize)]
 pub struct RpcSettings {
-    pub Url_Https: String,
-    pub Url_Wss: String,
+    #[serde(deserialize_with = "deserialize_url")]
+    pub Url_Https: Url,
+    #[serde(deserialize_with = "deserialize_url")]
+    pub Url_Wss: Url,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Default, Deserialize)]
+pub struct SellSettings {
+    /// Sell `tokenToBuy` back to ETH instead of buying it.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Fraction of each wallet's balance to sell, used for wallets without a
+    /// fixed amount. Defaults to the whole balance.
+    pub percentageOfBalance: Option<f64>,
+    /// Fixed number of whole tokens to sell per wallet.
+    #[serde(default)]
+    pub amountOfTokensToSell: HashMap<String, f64>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct SnipeSettings {
+    /// Hold the first round until liquidity for `tokenToBuy` shows up.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Watch the factory for `PairCreated` over `rpc.Url_Wss`.
+    #[serde(default = "default_true")]
+    pub watchPairCreated: bool,
+    /// Watch the mempool for pending `addLiquidity`/`addLiquidityETH` calls.
+    #[serde(default = "default_true")]
+    pub watchMempool: bool,
+    /// In private mode, bundle the pending liquidity add followed by one buy
+    /// per wallet for the first round.
+    #[serde(default = "default_true")]
+    pub backrun: bool,
+}
+
+impl Default for SnipeSettings {
+    fn default() -> Self {
+        SnipeSettings {
+            enabled: false,
+            watchPairCreated: true,
+            watchMempool: true,
+            backrun: true,
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct FlashbotsSettings {
+    /// Relay used to simulate bundles.
+    #[serde(default = "default_relay_url")]
+    pub relayUrl: String,
+    /// Builders every bundle is sent to.
+    #[serde(default = "default_builders")]
+    pub builders: Vec<BuilderSettings>,
+}
+
+impl Default for FlashbotsSettings {
+    fn default() -> Self {
+        FlashbotsSettings {
+            relayUrl: default_relay_url(),
+            builders: default_builders(),
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct BuilderSettings {
+    pub url: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Hex private key that signs bundles for this builder only.
+    pub signingKey: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct TreasurySettings {
+    /// Hex private key, needed to fund wallets.
+    pub privateKey: Option<String>,
+    /// Encrypted keystore path, as an alternative to `privateKey`.
+    pub keystore: Option<String>,
+    /// Sweep destination. Defaults to the treasury key's address.
+    pub address: Option<H160>,
+    /// ETH on top of `amountOfETHToBuy` each wallet gets per round for gas.
+    #[serde(default)]
+    pub gasBuffer: Wei,
+    /// Send funding and sweep transfers as one Flashbots bundle.
+    #[serde(default)]
+    pub useBundle: bool,
+}
+
+fn default_relay_url() -> String {
+    FLASHBOTS_RELAY_URL.to_string()
+}
+
+fn default_builders() -> Vec<BuilderSettings> {
+    BUILDER_URLS
+        .iter()
+        .map(|url| BuilderSettings {
+            url: url.to_string(),
+            enabled: true,
+            signingKey: None,
+        })
+        .collect()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bundle_block_budget() -> u64 {
+    5
+}
+
+/// Which trading subcommand is running. Each one adjusts the loaded
+/// settings before the command-line overrides are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapAction {
+    /// One round of buys.
+    Buy,
+    /// One round of sells.
+    Sell,
+    /// Wait for liquidity, then buy for `numberOfRounds`.
+    Snipe,
+    /// The settings as they are.
+    Volume,
+}
+
+impl SwapAction {
+    fn apply(self, settings: &mut Settings) {
+        match self {
+            SwapAction::Buy => {
+                settings.sell.enabled = false;
+                settings.snipe.enabled = false;
+                settings.numberOfRounds = 1;
+            }
+            SwapAction::Sell => {
+                settings.sell.enabled = true;
+                settings.snipe.enabled = false;
+                settings.numberOfRounds = 1;
+            }
+            SwapAction::Snipe => {
+                settings.sell.enabled = false;
+                settings.snipe.enabled = true;
+            }
+            SwapAction::Volume => {}
+        }
+    }
+}
+
+impl SellSettings {
+    pub fn amount_for(&self, wallet: &str) -> SellAmount {
+        match self.amountOfTokensToSell.get(wallet) {
+            Some(tokens) => SellAmount::Tokens(*tokens),
+            None => SellAmount::Percentage(self.percentageOfBalance.unwrap_or(1.0)),
+        }
+    }
 }
 
-pub async fn app() -> eyre::Result<(Settings, HashMap<String, LocalWallet>)> {
-    let mut file = File::open("settings.json")?;
+/// Loads the settings file named by `--config`, adjusts it for `action` and
+/// the command-line overrides, and loads the wallets and relay config.
+pub async fn app(
+    args: &CommonArgs,
+    action: Option<SwapAction>,
+) -> eyre::Result<(Settings, HashMap<String, LocalWallet>, RelayConfig)> {
+    let mut file = File::open(&args.config)
+        .map_err(|e| eyre::eyre!("Failed to open {}: {}", args.config.display(), e))?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
 
-    let settings: Settings = serde_json::from_str(&contents)?;
+    let value: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|e| eyre::eyre!("Failed to parse {}: {}", args.config.display(), e))?;
+    validate_settings(&value, args.overrides.amount.is_none())?;
+    let mut settings: Settings = serde_json::from_value(value)?;
+
+    if let Some(action) = action {
+        action.apply(&mut settings);
+    }
+    args.overrides.apply(&mut settings);
+    settings.dryRun |= args.dry_run;
 
     let mut wallet_secret_keys = HashMap::new();
 
     for (wallet, address) in settings.wallets.iter() {
-        let wallet_private_key_bytes = match decode(address) {
-            Ok(bytes) => bytes,
-            Err(_) => {
+        wallet_secret_keys.insert(wallet.clone(), wallet_from_hex(wallet, address)?);
+    }
+
+    if !settings.keystores.is_empty() {
+        let password = keystore_password("Keystore password: ")?;
+
+        for (wallet, path) in settings.keystores.iter() {
+            if wallet_secret_keys.contains_key(wallet) {
                 return Err(eyre::eyre!(
-                    "Failed to decode WALLET_PRIVATE_KEY for {}",
+                    "Wallet {} is listed in both wallets and keystores",
                     wallet
-                ))
+                ));
             }
+
+            wallet_secret_keys.insert(
+                wallet.clone(),
+                load_keystore_wallet(wallet, path, &password)?,
+            );
+        }
+    }
+
+    if let Some(mnemonic) = &settings.mnemonic {
+        let derived_wallets = derive_mnemonic_wallets(
+            mnemonic.phrase.as_deref(),
+            &mnemonic.derivationPath,
+            &mnemonic.namePrefix,
+        )?;
+
+        for (wallet, secret_key) in derived_wallets {
+            if wallet_secret_keys.contains_key(&wallet) {
+                return Err(eyre::eyre!(
+                    "Derived wallet {} clashes with a configured wallet",
+                    wallet
+                ));
+            }
+
+            if !settings.amountOfETHToBuy.contains_key(&wallet) {
+                let amount = mnemonic.defaultAmountOfETHToBuy.ok_or_else(|| {
+                    eyre::eyre!(
+                        "No amountOfETHToBuy for {} and no mnemonic.defaultAmountOfETHToBuy",
+                        wallet
+                    )
+                })?;
+                settings.amountOfETHToBuy.insert(wallet.clone(), amount);
+            }
+
+            wallet_secret_keys.insert(wallet, secret_key);
+        }
+    }
+
+    args.overrides
+        .select_wallets(&mut settings, &mut wallet_secret_keys)?;
+
+    let relay_config = relay_config(&settings)?;
+
+    Ok((settings, wallet_secret_keys, relay_config))
+}
+
+fn wallet_from_hex(name: &str, private_key: &str) -> eyre::Result<LocalWallet> {
+    let wallet_private_key_bytes = match decode(private_key) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return Err(eyre::eyre!(
+                "Failed to decode WALLET_PRIVATE_KEY for {}",
+                name
+            ))
+        }
+    };
+
+    let wallet_secret_key = SecretKey::from_slice(&wallet_private_key_bytes).map_err(|e| {
+        CustomError(format!(
+            "Failed to create SecretKey from WALLET_PRIVATE_KEY for {}: {}",
+            name, e
+        ))
+    })?;
+
+    Ok(LocalWallet::from(wallet_secret_key))
+}
+
+/// Validates the relay and builder URLs and loads the bundle signer and
+/// per-builder signing keys.
+fn relay_config(settings: &Settings) -> eyre::Result<RelayConfig> {
+    let flashbots = &settings.flashbots;
+    let relay_url = parse_url(&flashbots.relayUrl)
+        .map_err(|e| eyre::eyre!("Invalid flashbots.relayUrl: {}", e))?;
+
+    let mut builders = Vec::new();
+    for builder in flashbots.builders.iter().filter(|b| b.enabled) {
+        let url = parse_url(&builder.url)
+            .map_err(|e| eyre::eyre!("Invalid builder URL {}: {}", builder.url, e))?;
+
+        let signer = match &builder.signingKey {
+            Some(key) => Some(wallet_from_hex(&builder.url, key)?),
+            None => None,
         };
 
-        let wallet_secret_key = SecretKey::from_slice(&wallet_private_key_bytes)
-            .map_err(|e| {
-                Box::new(CustomError(format!(
-                    "Failed to create SecretKey from WALLET_PRIVATE_KEY: {}",
-                    e
-                ))) as Box<dyn std::error::Error + Send>
-            })
-            .unwrap();
+        builders.push(BuilderConfig { url, signer });
+    }
 
-        wallet_secret_keys.insert(wallet.clone(), LocalWallet::from(wallet_secret_key));
+    if builders.is_empty() {
+        return Err(eyre::eyre!("No enabled builders in flashbots.builders"));
     }
 
-    Ok((settings, wallet_secret_keys))
+    let bundle_signer = match &settings.bundleSigner {
+        Some(key) => Some(wallet_from_hex("bundleSigner", key)?),
+        None => None,
+    };
+
+    Ok(RelayConfig {
+        relay_url,
+        builders,
+        bundle_signer,
+    })
 }
 
-pub async fn run_app_and_swap() -> eyre::Result<()> {
-    info!("Fetching JSON settings...");
-    let (settings, wallet_secret_keys) = match app().await {
-        Ok((settings, wallet_secret_keys)) => (settings, wallet_secret_keys),
-        Err(e) => {
-            println!("Error: {}", e);
-            return Err(e);
+/// Builds the bundle clients once, to be shared by every round,
+/// authenticated with the dedicated bundle signer rather than a trading
+/// wallet. Returns `None` when `private` is false.
+async fn load_bundle_client(
+    relay_config: &RelayConfig,
+    private: bool,
+    chain: &ChainConfig,
+    provider: &Arc<Provider<Http>>,
+) -> eyre::Result<Option<BundleClient>> {
+    if private && !chain.private_relay {
+        return Err(eyre::eyre!(
+            "{} has no private relay; bundles and the token safety check are unavailable",
+            chain.name
+        ));
+    }
+
+    match &relay_config.bundle_signer {
+        Some(bundle_signer) if private => Ok(Some(
+            load_flashbots_client_middleware(bundle_signer, Arc::clone(provider), relay_config)
+                .await
+                .map_err(|e| eyre::eyre!("Failed to load flashbots middleware: {}", e))?,
+        )),
+        None if private => Err(eyre::eyre!(
+            "bundleSigner is required for private transactions"
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// A bundle client used only to simulate. The relay wants every request
+/// signed but a simulation needs no reputation, so without a `bundleSigner`
+/// a throwaway key signs instead.
+async fn load_simulation_client(
+    relay_config: &RelayConfig,
+    chain: &ChainConfig,
+    provider: &Arc<Provider<Http>>,
+) -> eyre::Result<BundleClient> {
+    if !chain.private_relay {
+        return Err(eyre::eyre!(
+            "The token safety check simulates through a Flashbots relay, which {} does not have",
+            chain.name
+        ));
+    }
+
+    let signer = relay_config
+        .bundle_signer
+        .clone()
+        .unwrap_or_else(|| LocalWallet::new(&mut rand::thread_rng()));
+
+    load_flashbots_client_middleware(&signer, Arc::clone(provider), relay_config)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to load flashbots middleware: {}", e))
+}
+
+/// Runs the buy-then-sell simulation from one of the trading wallets and
+/// fails when the token cannot be sold or its taxes exceed the limits.
+/// Returns the measured taxes so quotes can account for them.
+async fn check_token_safety(
+    settings: &Settings,
+    chain: &ChainConfig,
+    wallet_secret_keys: &HashMap<String, LocalWallet>,
+    relay_config: &RelayConfig,
+    bundle_client: &Option<BundleClient>,
+    provider: &Arc<Provider<Http>>,
+    foreign_txs: Vec<Bytes>,
+) -> eyre::Result<TransferTax> {
+    let report = simulate_token(
+        settings,
+        chain,
+        wallet_secret_keys,
+        relay_config,
+        bundle_client,
+        provider,
+        foreign_txs,
+    )
+    .await?;
+
+    report.check(settings.safety.maxBuyTax, settings.safety.maxSellTax)?;
+
+    Ok(TransferTax::from(&report))
+}
+
+/// Simulates a buy of `safety.probeAmountOfETH` and an immediate sell from
+/// one of the trading wallets. The simulation needs the Flashbots
+/// middleware, so public runs build one here, see `load_simulation_client`.
+async fn simulate_token(
+    settings: &Settings,
+    chain: &ChainConfig,
+    wallet_secret_keys: &HashMap<String, LocalWallet>,
+    relay_config: &RelayConfig,
+    bundle_client: &Option<BundleClient>,
+    provider: &Arc<Provider<Http>>,
+    foreign_txs: Vec<Bytes>,
+) -> eyre::Result<TokenSafetyReport> {
+    let probe_wallet = wallet_secret_keys
+        .values()
+        .next()
+        .ok_or_else(|| eyre::eyre!("No wallet to run the safety check from"))?;
+
+    let simulation_client = match bundle_client {
+        Some(_) => None,
+        None => Some(load_simulation_client(relay_config, chain, provider).await?),
+    };
+    let client = bundle_client
+        .as_ref()
+        .or(simulation_client.as_ref())
+        .ok_or_else(|| eyre::eyre!("No Flashbots middleware for the safety check"))?;
+
+    info!("Simulating a buy and sell of {:?}...", settings.tokenToBuy);
+    let token_address = settings.token_address();
+    let trade = V2Trade {
+        token_address: &token_address,
+        slippage_percentage: settings.slippage.fraction(),
+        transfer_tax: TransferTax::default(),
+        route_override: None,
+        dex: &chain.v2,
+        weth: chain.wrapped_native,
+        route_intermediates: &chain.route_intermediates,
+    };
+
+    analyze_token(
+        foreign_txs,
+        settings.safety.probeAmountOfETH.0,
+        &trade,
+        probe_wallet,
+        Arc::clone(provider),
+        client,
+        chain.gas_model,
+    )
+    .await
+}
+
+/// Connects to `rpc.Url_Https`, detects the chain it serves (with the `dex`
+/// override, if any) and binds every wallet to its chain id
+/// so signatures replay-protect for it.
+async fn connect(
+    settings: &Settings,
+    wallet_secret_keys: &mut HashMap<String, LocalWallet>,
+) -> eyre::Result<(Arc<Provider<Http>>, ChainConfig)> {
+    let dex = settings.dex.as_ref().map(DexSettings::deployment);
+
+    let provider = Arc::new(Provider::<Http>::try_from(settings.rpc.Url_Https.as_str())?);
+    let chain = detect_chain(&provider, dex).await?;
+
+    for secret_key in wallet_secret_keys.values_mut() {
+        *secret_key = secret_key.clone().with_chain_id(chain.chain_id);
+    }
+
+    Ok((provider, chain))
+}
+
+fn send_settings<'a>(
+    settings: &Settings,
+    chain: &ChainConfig,
+    gas_policy: &'a GasLimitPolicy,
+) -> SendSettings<'a> {
+    SendSettings {
+        buy_extra_gas: settings.BuyExtraGas.0,
+        miner_tip: settings.MinerTip.0,
+        gas_policy,
+        gas_model: chain.gas_model,
+        dry_run: settings.dryRun,
+    }
+}
+
+fn bundle_target<'a>(
+    settings: &Settings,
+    bundle_client: &'a Option<BundleClient>,
+) -> Option<BundleTarget<'a>> {
+    bundle_client.as_ref().map(|client| BundleTarget {
+        client,
+        block_budget: settings.bundleBlockBudget,
+    })
+}
+
+fn treasury_settings(settings: &Settings) -> eyre::Result<&TreasurySettings> {
+    settings
+        .treasury
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("No treasury in settings"))
+}
+
+fn treasury_wallet(treasury: &TreasurySettings) -> eyre::Result<LocalWallet> {
+    match (&treasury.privateKey, &treasury.keystore) {
+        (Some(private_key), _) => wallet_from_hex("treasury", private_key),
+        (None, Some(path)) => {
+            let password = keystore_password("Treasury keystore password: ")?;
+            load_keystore_wallet("treasury", path, &password)
         }
+        (None, None) => Err(eyre::eyre!(
+            "treasury.privateKey or treasury.keystore is required to fund wallets"
+        )),
+    }
+}
+
+/// Sends every wallet enough ETH for `numberOfRounds` buys of
+/// `amountOfETHToBuy` plus `treasury.gasBuffer` each.
+pub async fn run_fund(args: &CommonArgs) -> eyre::Result<()> {
+    let (settings, mut wallet_secret_keys, relay_config) = app(args, None).await?;
+    let treasury = treasury_settings(&settings)?;
+
+    let (provider, chain) = connect(&settings, &mut wallet_secret_keys).await?;
+
+    let chain = &chain;
+    let treasury_wallet = treasury_wallet(treasury)?.with_chain_id(chain.chain_id);
+    let bundle_client =
+        load_bundle_client(&relay_config, treasury.useBundle, chain, &provider).await?;
+
+    let targets = wallet_secret_keys
+        .iter()
+        .map(|(wallet, secret_key)| {
+            let per_round = settings.amount_for(wallet)? + treasury.gasBuffer.0;
+            let required = per_round * U256::from(settings.numberOfRounds);
+            Ok((wallet.clone(), secret_key.address(), required))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    fund_wallets(
+        &treasury_wallet,
+        targets,
+        Arc::clone(&provider),
+        &send_settings(&settings, chain, &settings.gas.policy()),
+        bundle_target(&settings, &bundle_client),
+    )
+    .await
+}
+
+/// Sends every wallet's `tokenToBuy` balance and leftover ETH back to the
+/// treasury.
+pub async fn run_sweep(args: &CommonArgs) -> eyre::Result<()> {
+    let (settings, mut wallet_secret_keys, relay_config) = app(args, None).await?;
+    let treasury = treasury_settings(&settings)?;
+
+    let (provider, chain) = connect(&settings, &mut wallet_secret_keys).await?;
+
+    let chain = &chain;
+
+    let treasury_address = match treasury.address {
+        Some(address) => address,
+        None => treasury_wallet(treasury)?.address(),
     };
 
-    let provider = Arc::new(Provider::<Http>::try_from(&settings.rpc.Url_Https)?);
+    let bundle_client =
+        load_bundle_client(&relay_config, treasury.useBundle, chain, &provider).await?;
 
-    for _ in 0..settings.numberOfRounds {
-        for (wallet, secret_key) in wallet_secret_keys.iter() {
-            let value = U256::from((settings.amountOfETHToBuy[wallet] * 1e18 as f64) as u128);
-            let token_address = &settings.tokenToBuy;
-            let slippage_percentage = if settings.autoSlippage {
-                0.5
-            } else {
-                settings.slippage
-            };
-            let buy_extra_gas = U256::from((settings.BuyExtraGas * 1e9 as f64) as u128);
-            let miner_tip = U256::from((settings.MinerTip * 1e9 as f64) as u128);
-            let maxbuy_amount = value;
+    sweep_wallets(
+        treasury_address,
+        wallet_secret_keys.into_iter().collect(),
+        &settings.token_address(),
+        Arc::clone(&provider),
+        &send_settings(&settings, chain, &settings.gas.policy()),
+        bundle_target(&settings, &bundle_client),
+    )
+    .await
+}
 
-            if settings.PrivateTransaction {
-                uniswap_v2_bundler(
+/// Prints the balances of every wallet.
+pub async fn run_balances(args: &CommonArgs) -> eyre::Result<()> {
+    let (settings, mut wallet_secret_keys, _) = app(args, None).await?;
+    let (provider, _) = connect(&settings, &mut wallet_secret_keys).await?;
+
+    print_balances(
+        wallet_secret_keys
+            .iter()
+            .map(|(wallet, secret_key)| (wallet.clone(), secret_key.address()))
+            .collect(),
+        settings.tokenToBuy,
+        provider,
+    )
+    .await
+}
+
+/// Prints what each wallet's buy would receive and the minimum it would
+/// accept. Nothing is signed or sent.
+pub async fn run_quote(args: &CommonArgs) -> eyre::Result<()> {
+    let (settings, mut wallet_secret_keys, _) = app(args, None).await?;
+    let (provider, chain) = connect(&settings, &mut wallet_secret_keys).await?;
+    let chain = &chain;
+
+    let token_address = settings.tokenToBuy;
+    let route = settings.route_override(chain.wrapped_native)?;
+    let v3_route = v3_route_config(&settings, chain)?;
+
+    let mut wallets = wallet_secret_keys.iter().collect::<Vec<_>>();
+    wallets.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (wallet, secret_key) in wallets {
+        let value = settings.amount_for(wallet)?;
+        let slippage_percentage =
+            buy_slippage(&settings, chain, value, secret_key, Arc::clone(&provider)).await?;
+
+        let (path, amount_out) = match &v3_route {
+            Some(route_config) => {
+                let route =
+                    resolve_buy_route(token_address, route_config, Arc::clone(&provider)).await?;
+                let amount_out = quote_exact_input(
+                    &route,
                     value,
-                    token_address,
+                    route_config.deployment.quoter_v2,
+                    Arc::clone(&provider),
+                )
+                .await?;
+                (route.tokens, amount_out)
+            }
+            None => {
+                let router =
+                    load_uniswap_v2_mempool(chain.v2.router, secret_key, Arc::clone(&provider))
+                        .await
+                        .map_err(|e| {
+                            eyre::eyre!("Failed to load uniswap v2 mempool contract: {}", e)
+                        })?;
+                let trade = V2Trade {
+                    token_address: &settings.token_address(),
                     slippage_percentage,
-                    buy_extra_gas,
-                    miner_tip,
-                    maxbuy_amount,
-                    secret_key,
+                    transfer_tax: TransferTax::default(),
+                    route_override: route.as_deref(),
+                    dex: &chain.v2,
+                    weth: chain.wrapped_native,
+                    route_intermediates: &chain.route_intermediates,
+                };
+                let quote =
+                    quote_buy(value, U256::zero(), &trade, &router, Arc::clone(&provider)).await?;
+                (quote.path, quote.amount_out)
+            }
+        };
+
+        println!(
+            "{}: {} ETH -> {} tokens, minimum {} at {:.2}% slippage, via {:?}",
+            wallet,
+            format_ether(value),
+            amount_out,
+            apply_slippage(amount_out, slippage_percentage)?,
+            slippage_percentage * 100.0,
+            path
+        );
+    }
+
+    Ok(())
+}
+
+/// Runs the token safety simulation and prints the measured taxes, failing
+/// like a trading run would if they exceed the limits.
+pub async fn run_simulate(args: &CommonArgs) -> eyre::Result<()> {
+    let (settings, mut wallet_secret_keys, relay_config) = app(args, None).await?;
+    let (provider, chain) = connect(&settings, &mut wallet_secret_keys).await?;
+    let chain = &chain;
+
+    let report = simulate_token(
+        &settings,
+        chain,
+        &wallet_secret_keys,
+        &relay_config,
+        &None,
+        &provider,
+        Vec::new(),
+    )
+    .await?;
+
+    println!(
+        "Buy tax {:.2}%, sell tax {:.2}%",
+        report.buy_tax * 100.0,
+        report.sell_tax * 100.0
+    );
+    if let Some(failure) = &report.transfer_failure {
+        println!("Failure: {}", failure);
+    }
+
+    report.check(settings.safety.maxBuyTax, settings.safety.maxSellTax)
+}
+
+/// Loads the settings, wallets and relay config, connects to the RPC and
+/// checks everything a trading run would check before its first swap.
+pub async fn run_validate_config(args: &CommonArgs) -> eyre::Result<()> {
+    let (settings, mut wallet_secret_keys, relay_config) = app(args, None).await?;
+
+    let (provider, chain) = connect(&settings, &mut wallet_secret_keys).await?;
+
+    let chain = &chain;
+    settings.route_override(chain.wrapped_native)?;
+    v3_route_config(&settings, chain)?;
+    load_bundle_client(&relay_config, settings.PrivateTransaction, chain, &provider).await?;
+
+    println!(
+        "{} is valid: {} wallets on {}",
+        args.config.display(),
+        wallet_secret_keys.len(),
+        chain.name
+    );
+
+    Ok(())
+}
+
+/// The V3 route config when `uniswapV3.enabled`, checking that the chain has
+/// a V3 deployment.
+fn v3_route_config(
+    settings: &Settings,
+    chain: &ChainConfig,
+) -> eyre::Result<Option<V3RouteConfig>> {
+    match (settings.uniswapV3.enabled, chain.v3) {
+        (true, None) => Err(eyre::eyre!("Uniswap V3 is not deployed on {}", chain.name)),
+        (true, Some(v3)) => Ok(Some(
+            settings.uniswapV3.route_config(v3, chain.wrapped_native)?,
+        )),
+        (false, _) => Ok(None),
+    }
+}
+
+/// The slippage for buying with `value` wei from `wallet`: the fixed
+/// `slippage` setting, or the auto-slippage estimate when `autoSlippage` is
+/// on and the buy goes through the direct WETH pair.
+async fn buy_slippage(
+    settings: &Settings,
+    chain: &ChainConfig,
+    value: U256,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<f64> {
+    if !uses_auto_slippage(settings)
+        || !takes_direct_pair(settings, chain, value, TradeSide::Buy, wallet, &provider).await?
+    {
+        return Ok(settings.slippage.fraction());
+    }
+
+    estimate_slippage(settings, chain, value, TradeSide::Buy, provider).await
+}
+
+/// The slippage for selling `sell_amount` from `wallet`. Auto slippage
+/// resolves the amount against the wallet's balance first.
+async fn sell_slippage(
+    settings: &Settings,
+    chain: &ChainConfig,
+    sell_amount: SellAmount,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<f64> {
+    if !uses_auto_slippage(settings) {
+        return Ok(settings.slippage.fraction());
+    }
+
+    let erc20_contract = load_erc20_contract(settings.tokenToBuy, wallet, Arc::clone(&provider))
+        .await
+        .map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
+    let amount = resolve_sell_amount(&erc20_contract, wallet.address(), sell_amount).await?;
+
+    if !takes_direct_pair(settings, chain, amount, TradeSide::Sell, wallet, &provider).await? {
+        return Ok(settings.slippage.fraction());
+    }
+
+    estimate_slippage(settings, chain, amount, TradeSide::Sell, provider).await
+}
+
+/// Auto slippage reads the V2 pair, so V3 runs keep the fixed value.
+fn uses_auto_slippage(settings: &Settings) -> bool {
+    settings.autoSlippage && !settings.uniswapV3.enabled
+}
+
+/// Whether a V2 swap of `amount_in` goes through the direct WETH pair: the
+/// `route` setting when there is one, otherwise the best route found for it.
+/// Auto slippage only models that pair, so any other path keeps the fixed
+/// `slippage`.
+async fn takes_direct_pair(
+    settings: &Settings,
+    chain: &ChainConfig,
+    amount_in: U256,
+    side: TradeSide,
+    wallet: &LocalWallet,
+    provider: &Arc<Provider<Http>>,
+) -> eyre::Result<bool> {
+    let path = match settings.route_override(chain.wrapped_native)? {
+        Some(route) => route,
+        None => {
+            let router = load_uniswap_v2_mempool(chain.v2.router, wallet, Arc::clone(provider))
+                .await
+                .map_err(|e| eyre::eyre!("Failed to load uniswap v2 mempool contract: {}", e))?;
+            let (token_in, token_out) = match side {
+                TradeSide::Buy => (chain.wrapped_native, settings.tokenToBuy),
+                TradeSide::Sell => (settings.tokenToBuy, chain.wrapped_native),
+            };
+            let (path, _) = find_best_route(
+                amount_in,
+                token_in,
+                token_out,
+                &chain.route_intermediates,
+                chain.v2.factory,
+                &router,
+                Arc::clone(provider),
+            )
+            .await?;
+            path
+        }
+    };
+
+    if path.len() != 2 {
+        info!(
+            "{:?} goes through {} hops, using the fixed slippage instead of auto slippage",
+            side,
+            path.len() - 1
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// The auto-slippage estimate for a swap through the direct WETH pair, or
+/// the fixed `slippage` when `autoSlippage` is off.
+async fn estimate_slippage(
+    settings: &Settings,
+    chain: &ChainConfig,
+    amount_in: U256,
+    side: TradeSide,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<f64> {
+    if !uses_auto_slippage(settings) {
+        return Ok(settings.slippage.fraction());
+    }
+
+    let estimate = auto_slippage(
+        amount_in,
+        side,
+        settings.tokenToBuy,
+        &settings.autoSlippageLimits.limits(),
+        chain,
+        settings.rpc.Url_Wss.as_str(),
+        provider,
+    )
+    .await?;
+
+    info!(
+        "Auto slippage for {:?} {:.4} (price impact {:.4}, volatility {:.4}, pending buys {:.4})",
+        side,
+        estimate.slippage,
+        estimate.price_impact,
+        estimate.volatility,
+        estimate.pending_impact
+    );
+
+    Ok(estimate.slippage)
+}
+
+pub async fn run_app_and_swap(args: &CommonArgs, action: SwapAction) -> eyre::Result<()> {
+    info!("Fetching JSON settings...");
+    let (settings, mut wallet_secret_keys, relay_config) = app(args, Some(action)).await?;
+
+    // Every contract address comes from the detected chain
+    let (provider, chain) = connect(&settings, &mut wallet_secret_keys).await?;
+    let chain = &chain;
+    let gas_policy = settings.gas.policy();
+    let route = settings.route_override(chain.wrapped_native)?;
+    let v3_route = v3_route_config(&settings, chain)?;
+
+    let bundle_client =
+        load_bundle_client(&relay_config, settings.PrivateTransaction, chain, &provider).await?;
+    let bundle_target = bundle_target(&settings, &bundle_client);
+    let send = send_settings(&settings, chain, &gas_policy);
+
+    // Public dry runs simulate through the relay too, so a sell sees its
+    // approval and buys are measured the same way as in a bundle
+    let simulation_client = match (&bundle_client, settings.dryRun && chain.private_relay) {
+        (None, true) => Some(load_simulation_client(&relay_config, chain, &provider).await?),
+        _ => None,
+    };
+
+    // Sells only need gas, plus the router approval
+    let preflight = check_wallets(
+        wallet_secret_keys
+            .iter()
+            .map(|(wallet, secret_key)| {
+                let value = match settings.sell.enabled {
+                    true => U256::zero(),
+                    false => settings.amount_for(wallet)?,
+                };
+                Ok((wallet.clone(), secret_key.address(), value))
+            })
+            .collect::<eyre::Result<_>>()?,
+        settings.numberOfRounds,
+        settings.sell.enabled,
+        settings.BuyExtraGas.0,
+        &gas_policy,
+        chain.gas_model,
+        Arc::clone(&provider),
+    )
+    .await?;
+
+    let underfunded: Vec<&str> = preflight
+        .iter()
+        .filter(|wallet| !wallet.is_funded())
+        .map(|wallet| wallet.name.as_str())
+        .collect();
+
+    if !underfunded.is_empty() {
+        match settings.underfundedWallets {
+            UnderfundedPolicy::Abort => {
+                return Err(eyre::eyre!(
+                    "Underfunded wallets: {}",
+                    underfunded.join(", ")
+                ))
+            }
+            UnderfundedPolicy::Skip => {
+                println!("Skipping underfunded wallets: {}", underfunded.join(", "));
+                wallet_secret_keys.retain(|wallet, _| !underfunded.contains(&wallet.as_str()));
+            }
+        }
+    }
+
+    if wallet_secret_keys.is_empty() {
+        return Err(eyre::eyre!("No wallets left to trade with"));
+    }
+
+    let mut first_round = 0;
+
+    let check_safety = settings.safety.enabled && !settings.sell.enabled;
+    let mut transfer_tax = TransferTax::default();
+
+    if check_safety && !settings.snipe.enabled {
+        transfer_tax = check_token_safety(
+            &settings,
+            chain,
+            &wallet_secret_keys,
+            &relay_config,
+            &bundle_client,
+            &provider,
+            Vec::new(),
+        )
+        .await?;
+    }
+
+    if settings.snipe.enabled {
+        info!("Waiting for liquidity on {:?}...", settings.tokenToBuy);
+        let launch = wait_for_launch(
+            settings.rpc.Url_Wss.as_str(),
+            settings.tokenToBuy,
+            &chain.v2,
+            chain.wrapped_native,
+            settings.snipe.watchPairCreated,
+            settings.snipe.watchMempool,
+        )
+        .await?;
+        match &launch {
+            LaunchEvent::PairCreated { pair, block_number } => {
+                println!(
+                    "Launch detected: pair {:?} created in block {:?}",
+                    pair, block_number
+                )
+            }
+            LaunchEvent::PendingAddLiquidity { tx_hash, .. } => {
+                println!("Launch detected: pending liquidity add {:?}", tx_hash)
+            }
+        }
+
+        // Only a bundle lands in the same block as a pending liquidity add.
+        // Public buys, and any buy after a bare PairCreated, would find no
+        // reserves until the liquidity is on chain.
+        let backrun = match (&launch, bundle_target) {
+            (LaunchEvent::PendingAddLiquidity { .. }, Some(_)) => settings.snipe.backrun,
+            _ => false,
+        };
+        if !backrun {
+            wait_for_liquidity(settings.rpc.Url_Wss.as_str(), &launch).await?;
+        }
+
+        if check_safety {
+            let foreign_txs = match &launch {
+                LaunchEvent::PendingAddLiquidity { raw_tx, .. } if backrun => vec![raw_tx.clone()],
+                _ => Vec::new(),
+            };
+
+            transfer_tax = check_token_safety(
+                &settings,
+                chain,
+                &wallet_secret_keys,
+                &relay_config,
+                &bundle_client,
+                &provider,
+                foreign_txs,
+            )
+            .await?;
+        }
+
+        // Backrun the pending liquidity add with every wallet's first buy
+        if let LaunchEvent::PendingAddLiquidity {
+            raw_tx,
+            added_liquidity,
+            ..
+        } = launch
+        {
+            if let (Some(target), true) = (bundle_target, backrun) {
+                // Fixed slippage: the pair has no reserves or history to estimate
+                // from until the liquidity add lands
+                let slippage_percentage = settings.slippage.fraction();
+                let buys = wallet_secret_keys
+                    .iter()
+                    .map(|(wallet, secret_key)| {
+                        settings
+                            .amount_for(wallet)
+                            .map(|value| (secret_key, value, slippage_percentage))
+                    })
+                    .collect::<eyre::Result<_>>()?;
+
+                let trade = V2Trade {
+                    token_address: &settings.token_address(),
+                    slippage_percentage,
+                    transfer_tax,
+                    route_override: None,
+                    dex: &chain.v2,
+                    weth: chain.wrapped_native,
+                    route_intermediates: &chain.route_intermediates,
+                };
+
+                uniswap_v2_multi_wallet_bundler(
+                    vec![raw_tx],
+                    added_liquidity,
+                    buys,
+                    &trade,
                     Arc::clone(&provider),
+                    &send,
+                    target,
                 )
                 .await?;
-            } else {
-                uniswap_v2_transaction(
+
+                first_round = 1;
+            }
+        }
+    }
+
+    for _ in first_round..settings.numberOfRounds {
+        // Single bundles plan every buy against the direct V2 pair; the
+        // settings validation rejects them together with a route or V3
+        if let (Some(target), true, false) =
+            (bundle_target, settings.singleBundle, settings.sell.enabled)
+        {
+            // Each swap gets the slippage for its own amount, estimated on
+            // the direct pair the bundle always buys through
+            let mut buys = Vec::with_capacity(wallet_secret_keys.len());
+            for (wallet, secret_key) in wallet_secret_keys.iter() {
+                let value = settings.amount_for(wallet)?;
+                let slippage_percentage = estimate_slippage(
+                    &settings,
+                    chain,
                     value,
-                    token_address,
-                    slippage_percentage,
-                    buy_extra_gas,
-                    miner_tip,
-                    maxbuy_amount,
+                    TradeSide::Buy,
+                    Arc::clone(&provider),
+                )
+                .await?;
+                buys.push((secret_key, value, slippage_percentage));
+            }
+
+            let trade = V2Trade {
+                token_address: &settings.token_address(),
+                slippage_percentage: settings.slippage.fraction(),
+                transfer_tax,
+                route_override: None,
+                dex: &chain.v2,
+                weth: chain.wrapped_native,
+                route_intermediates: &chain.route_intermediates,
+            };
+
+            uniswap_v2_multi_wallet_bundler(
+                Vec::new(),
+                None,
+                buys,
+                &trade,
+                Arc::clone(&provider),
+                &send,
+                target,
+            )
+            .await?;
+
+            // One simulated round shows what every round would send
+            if settings.dryRun {
+                break;
+            }
+
+            sleep(Duration::from_secs(settings.delayBetweenEachWalletBuy)).await;
+            continue;
+        }
+
+        let token_address = settings.token_address();
+        for (wallet, secret_key) in wallet_secret_keys.iter() {
+            if settings.sell.enabled {
+                let sell_amount = settings.sell.amount_for(wallet);
+                let slippage_percentage = sell_slippage(
+                    &settings,
+                    chain,
+                    sell_amount,
                     secret_key,
                     Arc::clone(&provider),
                 )
                 .await?;
+
+                if let Some(route_config) = &v3_route {
+                    let trade = V3Trade {
+                        token_address: &token_address,
+                        slippage_percentage,
+                        route_config,
+                    };
+
+                    match bundle_target {
+                        Some(target) => {
+                            uniswap_v3_sell_bundler(
+                                sell_amount,
+                                &trade,
+                                secret_key,
+                                Arc::clone(&provider),
+                                &send,
+                                target,
+                            )
+                            .await?
+                        }
+                        None => {
+                            uniswap_v3_sell_transaction(
+                                sell_amount,
+                                &trade,
+                                secret_key,
+                                Arc::clone(&provider),
+                                simulation_client.as_ref(),
+                                &send,
+                            )
+                            .await?
+                        }
+                    }
+                } else {
+                    let trade = V2Trade {
+                        token_address: &token_address,
+                        slippage_percentage,
+                        transfer_tax,
+                        route_override: route.as_deref(),
+                        dex: &chain.v2,
+                        weth: chain.wrapped_native,
+                        route_intermediates: &chain.route_intermediates,
+                    };
+
+                    match bundle_target {
+                        Some(target) => {
+                            uniswap_v2_sell_bundler(
+                                sell_amount,
+                                &trade,
+                                secret_key,
+                                Arc::clone(&provider),
+                                &send,
+                                target,
+                            )
+                            .await?
+                        }
+                        None => {
+                            uniswap_v2_sell_transaction(
+                                sell_amount,
+                                &trade,
+                                secret_key,
+                                Arc::clone(&provider),
+                                simulation_client.as_ref(),
+                                &send,
+                            )
+                            .await?
+                        }
+                    }
+                }
+                continue;
             }
+
+            let value = settings.amount_for(wallet)?;
+            let slippage_percentage =
+                buy_slippage(&settings, chain, value, secret_key, Arc::clone(&provider)).await?;
+            let maxbuy_amount = value;
+
+            if let Some(route_config) = &v3_route {
+                let trade = V3Trade {
+                    token_address: &token_address,
+                    slippage_percentage,
+                    route_config,
+                };
+
+                match bundle_target {
+                    Some(target) => {
+                        uniswap_v3_bundler(
+                            value,
+                            &trade,
+                            secret_key,
+                            Arc::clone(&provider),
+                            &send,
+                            target,
+                        )
+                        .await?
+                    }
+                    None => {
+                        uniswap_v3_transaction(
+                            value,
+                            &trade,
+                            secret_key,
+                            Arc::clone(&provider),
+                            simulation_client.as_ref(),
+                            &send,
+                        )
+                        .await?
+                    }
+                }
+            } else {
+                let trade = V2Trade {
+                    token_address: &token_address,
+                    slippage_percentage,
+                    transfer_tax,
+                    route_override: route.as_deref(),
+                    dex: &chain.v2,
+                    weth: chain.wrapped_native,
+                    route_intermediates: &chain.route_intermediates,
+                };
+
+                match bundle_target {
+                    Some(target) => {
+                        uniswap_v2_bundler(
+                            value,
+                            maxbuy_amount,
+                            &trade,
+                            secret_key,
+                            Arc::clone(&provider),
+                            &send,
+                            target,
+                        )
+                        .await?
+                    }
+                    None => {
+                        uniswap_v2_transaction(
+                            value,
+                            maxbuy_amount,
+                            &trade,
+                            secret_key,
+                            Arc::clone(&provider),
+                            simulation_client.as_ref(),
+                            &send,
+                        )
+                        .await?
+                    }
+                }
+            }
+        }
+
+        if settings.dryRun {
+            break;
         }
 
         // Delay between each round.
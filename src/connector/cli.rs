@@ -0,0 +1,194 @@
+use clap::{Args, Parser, Subcommand};
+use ethers::{signers::LocalWallet, types::H160};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::connector::app::Settings;
+use crate::connector::schema::{parse_address, parse_gwei, parse_http_url, SlippageBps, Wei};
+use crate::connector::wallets::KEYSTORE_DIR;
+
+#[derive(Debug, Parser)]
+#[command(name = "eth_volume_bot", about = "Uniswap volume and sniping bot")]
+pub struct Cli {
+    /// Runs `volume` when no subcommand is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    // Global, so every subcommand reads these whichever side of it they are on
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Buy `tokenToBuy` once from every wallet.
+    Buy,
+    /// Sell `tokenToBuy` back to ETH once from every wallet.
+    Sell,
+    /// Wait for liquidity on `tokenToBuy`, then buy for `numberOfRounds`.
+    Snipe,
+    /// Run the settings as configured: `numberOfRounds` rounds of buys, or
+    /// sells when `sell.enabled`.
+    Volume,
+    /// Send every wallet its buys plus gas from the treasury.
+    Fund,
+    /// Send every wallet's tokens and leftover ETH back to the treasury.
+    Sweep,
+    /// Print every wallet's ETH and `tokenToBuy` balances.
+    Balances,
+    /// Print what each wallet's buy would receive, without sending anything.
+    Quote,
+    /// Simulate a buy and sell of `tokenToBuy` and print the measured taxes.
+    Simulate,
+    /// Load and check the settings, wallets and RPC without trading.
+    ValidateConfig,
+    /// Manage encrypted keystores.
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WalletCommand {
+    /// Generate a new key and save it as an encrypted keystore.
+    New {
+        name: String,
+        #[arg(default_value = KEYSTORE_DIR)]
+        dir: String,
+    },
+    /// Encrypt an existing hex private key into a keystore.
+    Import {
+        name: String,
+        #[arg(default_value = KEYSTORE_DIR)]
+        dir: String,
+    },
+}
+
+/// Options every trading subcommand accepts, before or after its name.
+#[derive(Debug, Clone, Args)]
+pub struct CommonArgs {
+    /// Settings file to load.
+    #[arg(long, default_value = "settings.json", global = true)]
+    pub config: PathBuf,
+    /// Print what would be sent instead of broadcasting anything.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub overrides: SettingsOverrides,
+}
+
+/// One-off replacements for settings fields, applied after the file is
+/// loaded.
+#[derive(Debug, Clone, Default, Args)]
+pub struct SettingsOverrides {
+    /// Token to trade instead of `tokenToBuy`.
+    #[arg(long, value_parser = parse_address, global = true)]
+    pub token: Option<H160>,
+    /// ETH every wallet buys with, replacing `amountOfETHToBuy`.
+    #[arg(long, global = true)]
+    pub amount: Option<Wei>,
+    /// Only trade from these wallets; repeat for several.
+    #[arg(long = "wallet", global = true)]
+    pub wallets: Vec<String>,
+    /// Fixed slippage as a fraction; turns `autoSlippage` off.
+    #[arg(long, global = true)]
+    pub slippage: Option<SlippageBps>,
+    /// Number of rounds instead of `numberOfRounds`.
+    #[arg(long, global = true)]
+    pub rounds: Option<u32>,
+    /// Send bundles through the relay.
+    #[arg(long, conflicts_with = "public", global = true)]
+    pub private: bool,
+    /// Send public transactions.
+    #[arg(long, global = true)]
+    pub public: bool,
+    /// Gwei added to the max fee, replacing `BuyExtraGas`.
+    #[arg(long, value_parser = parse_gwei, global = true)]
+    pub extra_gas: Option<Wei>,
+    /// Priority fee in gwei, replacing `MinerTip`.
+    #[arg(long, value_parser = parse_gwei, global = true)]
+    pub miner_tip: Option<Wei>,
+    /// Fraction of each wallet's balance to sell, replacing
+    /// `sell.percentageOfBalance`.
+    #[arg(long, value_parser = parse_sell_percentage, global = true)]
+    pub sell_percentage: Option<f64>,
+    /// HTTP RPC URL instead of `rpc.Url_Https`.
+    #[arg(long, value_parser = parse_http_url, global = true)]
+    pub rpc_url: Option<Url>,
+}
+
+fn parse_sell_percentage(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        _ => Err(format!(
+            "expected a fraction above 0 and at most 1, got {}",
+            s
+        )),
+    }
+}
+
+impl SettingsOverrides {
+    /// Applies every override except `--amount` and `--wallet`, which need
+    /// the loaded wallets, see `select_wallets`.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(token) = self.token {
+            settings.tokenToBuy = token;
+        }
+        if let Some(slippage) = self.slippage {
+            settings.slippage = slippage;
+            settings.autoSlippage = false;
+        }
+        if let Some(rounds) = self.rounds {
+            settings.numberOfRounds = rounds;
+        }
+        if self.private {
+            settings.PrivateTransaction = true;
+        }
+        if self.public {
+            settings.PrivateTransaction = false;
+        }
+        if let Some(extra_gas) = self.extra_gas {
+            settings.BuyExtraGas = extra_gas;
+        }
+        if let Some(miner_tip) = self.miner_tip {
+            settings.MinerTip = miner_tip;
+        }
+        if let Some(percentage) = self.sell_percentage {
+            settings.sell.percentageOfBalance = Some(percentage);
+        }
+        if let Some(rpc_url) = &self.rpc_url {
+            settings.rpc.Url_Https = rpc_url.clone();
+        }
+    }
+
+    /// Sets every loaded wallet's `amountOfETHToBuy` to `--amount` and drops
+    /// the wallets not named with `--wallet`.
+    pub fn select_wallets(
+        &self,
+        settings: &mut Settings,
+        wallet_secret_keys: &mut HashMap<String, LocalWallet>,
+    ) -> eyre::Result<()> {
+        if let Some(amount) = self.amount {
+            for wallet in wallet_secret_keys.keys() {
+                settings.amountOfETHToBuy.insert(wallet.clone(), amount);
+            }
+        }
+
+        if self.wallets.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(unknown) = self
+            .wallets
+            .iter()
+            .find(|wallet| !wallet_secret_keys.contains_key(*wallet))
+        {
+            return Err(eyre::eyre!(
+                "--wallet {} is not a configured wallet",
+                unknown
+            ));
+        }
+
+        wallet_secret_keys.retain(|wallet, _| self.wallets.contains(wallet));
+        Ok(())
+    }
+}
@@ -1 +1,4 @@
 pub mod app;
+pub mod cli;
+pub mod schema;
+pub mod wallets;
@@ -0,0 +1,676 @@
+use ethers::{
+    types::{H160, H256, U256},
+    utils::{format_ether, parse_units},
+};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use std::{fmt, str::FromStr};
+use url::Url;
+
+use crate::core::{contracts::ETH_ADDRESS_REGEX, v3::V3_FEE_TIERS};
+
+/// Highest slippage the settings or `--slippage` may ask for, 50%.
+pub const MAX_SLIPPAGE_BPS: u32 = 5_000;
+
+/// Slippage tolerance in basis points, written as a fraction in the settings
+/// (`0.01` is 100 bps).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlippageBps(pub u32);
+
+impl SlippageBps {
+    pub fn from_fraction(fraction: f64) -> Result<Self, String> {
+        let max = MAX_SLIPPAGE_BPS as f64 / 10_000.0;
+        if !fraction.is_finite() || !(0.0..=max).contains(&fraction) {
+            return Err(format!(
+                "expected a slippage fraction between 0 and {}, got {}",
+                max, fraction
+            ));
+        }
+
+        Ok(SlippageBps((fraction * 10_000.0).round() as u32))
+    }
+
+    /// The tolerance as a fraction, as the swap functions take it.
+    pub fn fraction(self) -> f64 {
+        self.0 as f64 / 10_000.0
+    }
+}
+
+impl FromStr for SlippageBps {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fraction = s
+            .parse::<f64>()
+            .map_err(|e| format!("invalid slippage {}: {}", s, e))?;
+        SlippageBps::from_fraction(fraction)
+    }
+}
+
+impl<'de> Deserialize<'de> for SlippageBps {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        SlippageBps::from_fraction(f64::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// An amount of ETH, held in wei. Deserializes from a number of ETH; gas
+/// settings given in gwei use `deserialize_gwei`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Wei(pub U256);
+
+impl Wei {
+    pub fn from_eth(eth: f64) -> Result<Self, String> {
+        Wei::from_units(eth, "ether", "ETH")
+    }
+
+    pub fn from_gwei(gwei: f64) -> Result<Self, String> {
+        Wei::from_units(gwei, "gwei", "gwei")
+    }
+
+    /// Converts through the decimal string rather than multiplying floats,
+    /// so `0.1` ETH is exactly 10^17 wei.
+    fn from_units(amount: f64, units: &str, name: &str) -> Result<Self, String> {
+        if !amount.is_finite() || amount < 0.0 {
+            return Err(format!(
+                "expected a non-negative amount of {}, got {}",
+                name, amount
+            ));
+        }
+
+        parse_units(amount.to_string(), units)
+            .map(|parsed| Wei(parsed.into()))
+            .map_err(|e| format!("invalid amount of {} {}: {}", name, amount, e))
+    }
+}
+
+impl From<Wei> for U256 {
+    fn from(wei: Wei) -> U256 {
+        wei.0
+    }
+}
+
+impl fmt::Display for Wei {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ETH", format_ether(self.0))
+    }
+}
+
+impl FromStr for Wei {
+    type Err = String;
+
+    /// Parses a number of ETH.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let eth = s
+            .parse::<f64>()
+            .map_err(|e| format!("invalid amount {}: {}", s, e))?;
+        Wei::from_eth(eth)
+    }
+}
+
+impl<'de> Deserialize<'de> for Wei {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Wei::from_eth(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// For settings given in gwei, such as `BuyExtraGas` and `MinerTip`.
+pub fn deserialize_gwei<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Wei, D::Error> {
+    Wei::from_gwei(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+pub fn deserialize_url<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Url, D::Error> {
+    let url = String::deserialize(deserializer)?;
+    Url::parse(&url).map_err(|e| serde::de::Error::custom(format!("invalid URL {}: {}", url, e)))
+}
+
+/// Parses a `0x`-prefixed address, for command-line flags.
+pub fn parse_address(s: &str) -> Result<H160, String> {
+    if !ETH_ADDRESS_REGEX.is_match(s) {
+        return Err(format!("expected a 0x-prefixed 20-byte address, got {}", s));
+    }
+    H160::from_str(s).map_err(|e| format!("invalid address {}: {}", s, e))
+}
+
+/// Parses a number of gwei, for command-line flags.
+pub fn parse_gwei(s: &str) -> Result<Wei, String> {
+    let gwei = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid amount {}: {}", s, e))?;
+    Wei::from_gwei(gwei)
+}
+
+/// Parses an `http(s)` URL, for command-line flags.
+pub fn parse_http_url(s: &str) -> Result<Url, String> {
+    url_with_scheme(s, &["http", "https"])
+}
+
+/// One problem in the settings file, with the JSON path it was found at,
+/// e.g. `$.flashbots.builders[1].url`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Every problem `validate_settings` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsErrors(pub Vec<SettingsError>);
+
+impl fmt::Display for SettingsErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid settings:")?;
+        for error in self.0.iter() {
+            write!(f, "\n  {}: {}", error.path, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SettingsErrors {}
+
+/// Checks the raw settings JSON against the schema `Settings` expects,
+/// collecting every problem rather than stopping at the first. When
+/// `require_amounts` is set, every wallet in `wallets` and `keystores` must
+/// have an `amountOfETHToBuy` entry; `--amount` supplies them otherwise.
+pub fn validate_settings(settings: &Value, require_amounts: bool) -> Result<(), SettingsErrors> {
+    let root = match settings.as_object() {
+        Some(root) => root,
+        None => {
+            return Err(SettingsErrors(vec![SettingsError {
+                path: "$".to_string(),
+                message: "expected a JSON object".to_string(),
+            }]))
+        }
+    };
+
+    let mut check = Check::default();
+    let at = "$";
+
+    let wallets = check.map(root, at, "wallets", false, private_key);
+    let keystores = check.map(root, at, "keystores", false, |value| {
+        string(value).map(str::to_owned)
+    });
+    if let Some(mnemonic) = check.object(root, at, "mnemonic", false) {
+        let at = "$.mnemonic";
+        check.field(mnemonic, at, "phrase", false, string);
+        check.field(mnemonic, at, "derivationPath", true, string);
+        check.field(mnemonic, at, "namePrefix", false, string);
+        check.field(mnemonic, at, "defaultAmountOfETHToBuy", false, eth);
+    }
+
+    check.field(root, at, "tokenToBuy", true, address);
+    check.field(root, at, "slippage", true, slippage);
+    check.field(root, at, "autoSlippage", true, boolean);
+    if let Some(limits) = check.object(root, at, "autoSlippageLimits", false) {
+        let at = "$.autoSlippageLimits";
+        let min = check.field(limits, at, "minSlippage", false, slippage);
+        let max = check.field(limits, at, "maxSlippage", false, slippage);
+        check.field(limits, at, "lookbackBlocks", false, unsigned);
+        check.field(limits, at, "mempoolWindowMs", false, unsigned);
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                check.error(
+                    "$.autoSlippageLimits.minSlippage",
+                    format!("must not exceed maxSlippage ({})", max.fraction()),
+                );
+            }
+        }
+    }
+
+    let amounts = check.map(root, at, "amountOfETHToBuy", true, eth);
+    if let (true, Some(amounts)) = (require_amounts, amounts) {
+        let names = wallets.iter().chain(keystores.iter()).flatten();
+        for name in names.filter(|name| !amounts.contains(*name)) {
+            check.error(
+                &format!("$.amountOfETHToBuy.{}", name),
+                format!("is required, wallet {} has no amount to buy with", name),
+            );
+        }
+    }
+
+    check.field(root, at, "BuyExtraGas", true, gwei);
+    check.field(root, at, "MinerTip", true, gwei);
+    check.field(root, at, "delayBetweenEachWalletBuy", true, unsigned);
+    check.field(root, at, "numberOfRounds", true, |value| {
+        unsigned(value).and_then(|rounds| {
+            u32::try_from(rounds).map_err(|_| format!("expected at most {}", u32::MAX))
+        })
+    });
+    check.field(root, at, "PrivateTransaction", true, boolean);
+    let single_bundle = check.field(root, at, "singleBundle", false, boolean);
+    check.field(root, at, "bundleSigner", false, private_key);
+    check.field(root, at, "bundleBlockBudget", false, unsigned);
+    check.field(root, at, "dryRun", false, boolean);
+    check.field(root, at, "underfundedWallets", false, |value| {
+        match value.as_str() {
+            Some("abort") | Some("skip") => Ok(()),
+            _ => Err(format!("expected \"abort\" or \"skip\", got {}", value)),
+        }
+    });
+
+    if let Some(rpc) = check.object(root, at, "rpc", true) {
+        let at = "$.rpc";
+        check.field(rpc, at, "Url_Https", true, |value| {
+            url(value, &["http", "https"])
+        });
+        check.field(rpc, at, "Url_Wss", true, |value| url(value, &["ws", "wss"]));
+    }
+
+    if let Some(sell) = check.object(root, at, "sell", false) {
+        let at = "$.sell";
+        check.field(sell, at, "enabled", false, boolean);
+        check.field(sell, at, "percentageOfBalance", false, |value| {
+            fraction(value, "above 0 and at most 1", |f| f > 0.0 && f <= 1.0)
+        });
+        check.map(sell, at, "amountOfTokensToSell", false, |value| {
+            fraction(value, "at least 0", |f| f >= 0.0)
+        });
+    }
+
+    if let Some(snipe) = check.object(root, at, "snipe", false) {
+        let at = "$.snipe";
+        for key in ["enabled", "watchPairCreated", "watchMempool", "backrun"] {
+            check.field(snipe, at, key, false, boolean);
+        }
+    }
+
+    if let Some(flashbots) = check.object(root, at, "flashbots", false) {
+        let at = "$.flashbots";
+        check.field(flashbots, at, "relayUrl", false, |value| {
+            url(value, &["http", "https"])
+        });
+        if let Some(builders) = check.field(flashbots, at, "builders", false, array) {
+            for (index, builder) in builders.iter().enumerate() {
+                let at = format!("$.flashbots.builders[{}]", index);
+                let builder = match check.value(&at, builder, object) {
+                    Some(builder) => builder,
+                    None => continue,
+                };
+                check.field(builder, &at, "url", true, |value| {
+                    url(value, &["http", "https"])
+                });
+                check.field(builder, &at, "enabled", false, boolean);
+                check.field(builder, &at, "signingKey", false, private_key);
+            }
+        }
+    }
+
+    if let Some(treasury) = check.object(root, at, "treasury", false) {
+        let at = "$.treasury";
+        check.field(treasury, at, "privateKey", false, private_key);
+        check.field(treasury, at, "keystore", false, string);
+        check.field(treasury, at, "address", false, address);
+        check.field(treasury, at, "gasBuffer", false, eth);
+        check.field(treasury, at, "useBundle", false, boolean);
+    }
+
+    if let Some(safety) = check.object(root, at, "safety", false) {
+        let at = "$.safety";
+        check.field(safety, at, "enabled", false, boolean);
+        for key in ["maxBuyTax", "maxSellTax"] {
+            check.field(safety, at, key, false, |value| {
+                fraction(value, "between 0 and 1", |f| (0.0..=1.0).contains(&f))
+            });
+        }
+        check.field(safety, at, "probeAmountOfETH", false, eth);
+    }
+
+    if let Some(gas) = check.object(root, at, "gas", false) {
+        let at = "$.gas";
+        check.field(gas, at, "multiplier", false, |value| {
+            fraction(value, "above 0", |f| f > 0.0)
+        });
+        check.field(gas, at, "cap", false, unsigned);
+        check.field(gas, at, "fallback", false, unsigned);
+    }
+
+    let mut uniswap_v3_enabled = None;
+    if let Some(uniswap_v3) = check.object(root, at, "uniswapV3", false) {
+        let at = "$.uniswapV3";
+        uniswap_v3_enabled = check.field(uniswap_v3, at, "enabled", false, boolean);
+        check.field(uniswap_v3, at, "feeTier", false, |value| {
+            match value.as_u64() {
+                Some(fee) if V3_FEE_TIERS.iter().any(|tier| *tier as u64 == fee) => Ok(()),
+                _ => Err(format!("expected one of {:?}, got {}", V3_FEE_TIERS, value)),
+            }
+        });
+        check.addresses(uniswap_v3, at, "via");
+    }
+
+    let route = check.addresses(root, at, "route");
+    if let Some(hops) = route {
+        if hops < 2 {
+            check.error("$.route", "needs at least WETH and tokenToBuy");
+        }
+    }
+
+    // Single bundles only buy through the direct V2 pair
+    if single_bundle == Some(true) {
+        if route.is_some() {
+            check.error("$.singleBundle", "cannot be combined with a route");
+        }
+        if uniswap_v3_enabled == Some(true) {
+            check.error("$.singleBundle", "cannot be combined with uniswapV3");
+        }
+    }
+
+    if let Some(dex) = check.object(root, at, "dex", false) {
+        let at = "$.dex";
+        check.field(dex, at, "router", true, address);
+        check.field(dex, at, "factory", true, address);
+        check.field(dex, at, "initCodeHash", true, hash);
+        check.field(dex, at, "fee", false, |value| {
+            fraction(value, "at least 0 and below 0.1", |f| {
+                (0.0..0.1).contains(&f)
+            })
+        });
+    }
+
+    match check.errors.is_empty() {
+        true => Ok(()),
+        false => Err(SettingsErrors(check.errors)),
+    }
+}
+
+#[derive(Default)]
+struct Check {
+    errors: Vec<SettingsError>,
+}
+
+impl Check {
+    fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.errors.push(SettingsError {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn value<'a, T>(
+        &mut self,
+        path: &str,
+        value: &'a Value,
+        parse: impl FnOnce(&'a Value) -> Result<T, String>,
+    ) -> Option<T> {
+        match parse(value) {
+            Ok(parsed) => Some(parsed),
+            Err(message) => {
+                self.error(path, message);
+                None
+            }
+        }
+    }
+
+    /// Parses `object[key]`. A missing or `null` field is an error only
+    /// when `required`.
+    fn field<'a, T>(
+        &mut self,
+        object: &'a Map<String, Value>,
+        at: &str,
+        key: &str,
+        required: bool,
+        parse: impl FnOnce(&'a Value) -> Result<T, String>,
+    ) -> Option<T> {
+        let path = format!("{}.{}", at, key);
+        match object.get(key) {
+            None | Some(Value::Null) if required => {
+                self.error(&path, "is required");
+                None
+            }
+            None | Some(Value::Null) => None,
+            Some(value) => self.value(&path, value, parse),
+        }
+    }
+
+    fn object<'a>(
+        &mut self,
+        object: &'a Map<String, Value>,
+        at: &str,
+        key: &str,
+        required: bool,
+    ) -> Option<&'a Map<String, Value>> {
+        self.field(object, at, key, required, self::object)
+    }
+
+    /// Checks every value of the name-keyed map at `object[key]` and returns
+    /// its names.
+    fn map<T>(
+        &mut self,
+        object: &Map<String, Value>,
+        at: &str,
+        key: &str,
+        required: bool,
+        parse: impl Fn(&Value) -> Result<T, String>,
+    ) -> Option<Vec<String>> {
+        let map = self.object(object, at, key, required)?;
+        for (name, value) in map.iter() {
+            self.value(&format!("{}.{}.{}", at, key, name), value, &parse);
+        }
+        Some(map.keys().cloned().collect())
+    }
+
+    /// Checks the optional address list at `object[key]` and returns its
+    /// length.
+    fn addresses(&mut self, object: &Map<String, Value>, at: &str, key: &str) -> Option<usize> {
+        let tokens = self.field(object, at, key, false, array)?;
+        for (index, token) in tokens.iter().enumerate() {
+            self.value(&format!("{}.{}[{}]", at, key, index), token, address);
+        }
+        Some(tokens.len())
+    }
+}
+
+fn object(value: &Value) -> Result<&Map<String, Value>, String> {
+    value
+        .as_object()
+        .ok_or_else(|| format!("expected an object, got {}", value))
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("expected an array, got {}", value))
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("expected a string, got {}", value))
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("expected true or false, got {}", value))
+}
+
+fn unsigned(value: &Value) -> Result<u64, String> {
+    value
+        .as_u64()
+        .ok_or_else(|| format!("expected a non-negative integer, got {}", value))
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("expected a number, got {}", value))
+}
+
+fn fraction(value: &Value, bounds: &str, within: impl Fn(f64) -> bool) -> Result<f64, String> {
+    let number = number(value)?;
+    match within(number) {
+        true => Ok(number),
+        false => Err(format!("expected a number {}, got {}", bounds, number)),
+    }
+}
+
+fn slippage(value: &Value) -> Result<SlippageBps, String> {
+    SlippageBps::from_fraction(number(value)?)
+}
+
+fn eth(value: &Value) -> Result<Wei, String> {
+    Wei::from_eth(number(value)?)
+}
+
+fn gwei(value: &Value) -> Result<Wei, String> {
+    Wei::from_gwei(number(value)?)
+}
+
+fn address(value: &Value) -> Result<H160, String> {
+    parse_address(string(value)?)
+}
+
+fn hash(value: &Value) -> Result<H256, String> {
+    let hash = string(value)?;
+    match hash.len() == 66 && hash.starts_with("0x") {
+        true => H256::from_str(hash).map_err(|e| format!("invalid hash {}: {}", hash, e)),
+        false => Err(format!("expected a 0x-prefixed 32-byte hash, got {}", hash)),
+    }
+}
+
+/// Never echoes the key, only whether it decodes.
+fn private_key(value: &Value) -> Result<(), String> {
+    let key = value
+        .as_str()
+        .ok_or_else(|| "expected a hex private key string".to_string())?;
+    match hex::decode(key) {
+        Ok(bytes) if bytes.len() == 32 => Ok(()),
+        _ => Err("expected a 32-byte hex private key without 0x".to_string()),
+    }
+}
+
+fn url(value: &Value, schemes: &[&str]) -> Result<Url, String> {
+    url_with_scheme(string(value)?, schemes)
+}
+
+fn url_with_scheme(s: &str, schemes: &[&str]) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|e| format!("invalid URL {}: {}", s, e))?;
+    if !schemes.contains(&url.scheme()) {
+        return Err(format!(
+            "expected a {} URL, got {}",
+            schemes.join(" or "),
+            s
+        ));
+    }
+    if url.host_str().is_none() {
+        return Err(format!("URL {} has no host", s));
+    }
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::app::Settings;
+    use serde_json::json;
+
+    const PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const TOKEN: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+
+    /// The shipped `settings.json` with its placeholders filled in.
+    fn shipped_settings() -> Value {
+        let mut settings: Value =
+            serde_json::from_str(include_str!("../../settings.json")).unwrap();
+
+        for key in ["wallet1", "wallet2", "wallet3"] {
+            settings["wallets"][key] = json!(PRIVATE_KEY);
+        }
+        settings["tokenToBuy"] = json!(TOKEN);
+        settings["bundleSigner"] = json!(PRIVATE_KEY);
+        settings["treasury"]["privateKey"] = json!(PRIVATE_KEY);
+        settings
+    }
+
+    fn error_paths(settings: &Value) -> Vec<String> {
+        let mut paths: Vec<String> = match validate_settings(settings, true) {
+            Ok(()) => Vec::new(),
+            Err(SettingsErrors(errors)) => errors.into_iter().map(|error| error.path).collect(),
+        };
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn shipped_settings_validate_and_deserialize() {
+        let settings = shipped_settings();
+
+        assert_eq!(validate_settings(&settings, true), Ok(()));
+        serde_json::from_value::<Settings>(settings).unwrap();
+    }
+
+    #[test]
+    fn reports_every_bad_field() {
+        let mut settings = shipped_settings();
+        settings["wallets"]["wallet1"] = json!(format!("0x{}", PRIVATE_KEY));
+        settings["wallets"]["wallet2"] = json!(&PRIVATE_KEY[..62]);
+        settings["tokenToBuy"] = json!("0x1234");
+        settings["slippage"] = json!(0.8);
+        settings["BuyExtraGas"] = json!(-1);
+        settings["MinerTip"] = json!("5");
+        settings["rpc"]["Url_Https"] = json!("ftp://example.com");
+        settings["rpc"]["Url_Wss"] = json!("https://example.com");
+        settings["flashbots"]["builders"][1]["url"] = json!("not a url");
+
+        assert_eq!(
+            error_paths(&settings),
+            [
+                "$.BuyExtraGas",
+                "$.MinerTip",
+                "$.flashbots.builders[1].url",
+                "$.rpc.Url_Https",
+                "$.rpc.Url_Wss",
+                "$.slippage",
+                "$.tokenToBuy",
+                "$.wallets.wallet1",
+                "$.wallets.wallet2",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_fields_and_amounts() {
+        let mut settings = shipped_settings();
+        let root = settings.as_object_mut().unwrap();
+        root.remove("tokenToBuy");
+        root["amountOfETHToBuy"]
+            .as_object_mut()
+            .unwrap()
+            .remove("wallet3");
+
+        assert_eq!(
+            error_paths(&settings),
+            ["$.amountOfETHToBuy.wallet3", "$.tokenToBuy"]
+        );
+        // `--amount` supplies the missing amount
+        assert_eq!(
+            validate_settings(&settings, false)
+                .unwrap_err()
+                .0
+                .into_iter()
+                .map(|error| error.path)
+                .collect::<Vec<_>>(),
+            ["$.tokenToBuy"]
+        );
+    }
+
+    #[test]
+    fn rejects_single_bundle_with_a_route_or_v3() {
+        let mut settings = shipped_settings();
+        settings["singleBundle"] = json!(true);
+        assert_eq!(error_paths(&settings), Vec::<String>::new());
+
+        settings["route"] = json!([TOKEN, TOKEN]);
+        settings["uniswapV3"]["enabled"] = json!(true);
+        assert_eq!(error_paths(&settings), ["$.singleBundle", "$.singleBundle"]);
+    }
+
+    #[test]
+    fn slippage_is_capped_at_max_bps() {
+        assert_eq!(
+            SlippageBps::from_fraction(0.5),
+            Ok(SlippageBps(MAX_SLIPPAGE_BPS))
+        );
+        assert!(SlippageBps::from_fraction(0.5001).is_err());
+        assert!(SlippageBps::from_fraction(-0.01).is_err());
+    }
+}
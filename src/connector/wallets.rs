@@ -0,0 +1,201 @@
+use ethers::signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer};
+use std::{ops::RangeInclusive, path::Path};
+
+use crate::env::provider::{keystore_password_env, mnemonic_env};
+
+/// Directory `wallet new` and `wallet import` write keystores to by default.
+pub const KEYSTORE_DIR: &str = "./keystores";
+
+/// Reads the keystore password from `KEYSTORE_PASSWORD`, or prompts for it
+/// on the terminal when the variable is not set.
+pub fn keystore_password(prompt: &str) -> eyre::Result<String> {
+    match keystore_password_env() {
+        Ok(password) => Ok(password),
+        Err(_) => rpassword::prompt_password(prompt)
+            .map_err(|e| eyre::eyre!("Failed to read keystore password: {}", e)),
+    }
+}
+
+/// Decrypts a standard Ethereum JSON keystore (scrypt or pbkdf2).
+pub fn load_keystore_wallet(name: &str, path: &str, password: &str) -> eyre::Result<LocalWallet> {
+    LocalWallet::decrypt_keystore(path, password)
+        .map_err(|e| eyre::eyre!("Failed to decrypt keystore {} for {}: {}", path, name, e))
+}
+
+/// Generates a fresh key and writes it to `<dir>/<name>` as an encrypted
+/// keystore.
+pub fn new_keystore(name: &str, dir: &str) -> eyre::Result<LocalWallet> {
+    std::fs::create_dir_all(dir)?;
+    let password = keystore_password(&format!("Password for new keystore {}: ", name))?;
+
+    let (wallet, _) = LocalWallet::new_keystore(
+        Path::new(dir),
+        &mut rand::thread_rng(),
+        password,
+        Some(name),
+    )
+    .map_err(|e| eyre::eyre!("Failed to create keystore for {}: {}", name, e))?;
+
+    println!(
+        "Created keystore {}/{} for {:?}",
+        dir,
+        name,
+        wallet.address()
+    );
+
+    Ok(wallet)
+}
+
+/// Prompts for an existing hex private key and encrypts it into
+/// `<dir>/<name>`. The key is read from the terminal so it never ends up in
+/// shell history.
+pub fn import_keystore(name: &str, dir: &str) -> eyre::Result<LocalWallet> {
+    std::fs::create_dir_all(dir)?;
+    let private_key = rpassword::prompt_password(format!("Private key for {}: ", name))
+        .map_err(|e| eyre::eyre!("Failed to read private key: {}", e))?;
+    let private_key_bytes = hex::decode(private_key.trim_start_matches("0x"))
+        .map_err(|_| eyre::eyre!("Failed to decode private key for {}", name))?;
+    let password = keystore_password(&format!("Password for keystore {}: ", name))?;
+
+    let (wallet, _) = LocalWallet::encrypt_keystore(
+        Path::new(dir),
+        &mut rand::thread_rng(),
+        private_key_bytes,
+        password,
+        Some(name),
+    )
+    .map_err(|e| eyre::eyre!("Failed to import keystore for {}: {}", name, e))?;
+
+    println!(
+        "Imported {:?} into keystore {}/{}",
+        wallet.address(),
+        dir,
+        name
+    );
+
+    Ok(wallet)
+}
+
+/// Splits a derivation path whose last segment is an index range, e.g.
+/// `m/44'/60'/0'/0/0..49`, into its base path and the inclusive range. A
+/// plain index is a range of one.
+pub fn parse_derivation_range(path: &str) -> eyre::Result<(String, RangeInclusive<u32>)> {
+    let (base, last) = path
+        .rsplit_once('/')
+        .ok_or_else(|| eyre::eyre!("Invalid derivation path {}", path))?;
+
+    let parse_index = |index: &str| {
+        index
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| eyre::eyre!("Invalid derivation index {} in {}", index, path))
+    };
+
+    let range = match last.split_once("..") {
+        Some((start, end)) => parse_index(start)?..=parse_index(end)?,
+        None => {
+            let index = parse_index(last)?;
+            index..=index
+        }
+    };
+
+    if range.is_empty() {
+        return Err(eyre::eyre!("Empty derivation range in {}", path));
+    }
+
+    Ok((base.to_string(), range))
+}
+
+/// Derives one wallet per index in `derivation_path`'s range from a BIP-39
+/// mnemonic. Wallets are named `<name_prefix><index>`, so names stay stable
+/// when the range grows. The phrase falls back to `WALLET_MNEMONIC`.
+pub fn derive_mnemonic_wallets(
+    phrase: Option<&str>,
+    derivation_path: &str,
+    name_prefix: &str,
+) -> eyre::Result<Vec<(String, LocalWallet)>> {
+    let phrase = match phrase {
+        Some(phrase) => phrase.to_string(),
+        None => mnemonic_env()
+            .map_err(|_| eyre::eyre!("No mnemonic phrase in settings or WALLET_MNEMONIC"))?,
+    };
+
+    let (base_path, range) = parse_derivation_range(derivation_path)?;
+
+    range
+        .map(|index| {
+            let wallet = MnemonicBuilder::<English>::default()
+                .phrase(phrase.as_str())
+                .derivation_path(&format!("{}/{}", base_path, index))
+                .and_then(|builder| builder.build())
+                .map_err(|e| eyre::eyre!("Failed to derive wallet at index {}: {}", index, e))?;
+
+            Ok((format!("{}{}", name_prefix, index), wallet))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::H160;
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn parses_ranges_and_single_indexes() {
+        let (base, range) = parse_derivation_range("m/44'/60'/0'/0/0..49").unwrap();
+        assert_eq!(base, "m/44'/60'/0'/0");
+        assert_eq!(range, 0..=49);
+
+        let (base, range) = parse_derivation_range("m/44'/60'/0'/0/3").unwrap();
+        assert_eq!(base, "m/44'/60'/0'/0");
+        assert_eq!(range, 3..=3);
+
+        let (_, range) = parse_derivation_range("m/44'/60'/0'/0/5..5").unwrap();
+        assert_eq!(range, 5..=5);
+    }
+
+    #[test]
+    fn rejects_bad_derivation_paths() {
+        for path in [
+            "m/44'/60'/0'/0/5..0",
+            "m/44'/60'/0'/0/",
+            "m/44'/60'/0'/0/a..b",
+            "m/44'/60'/0'/0/0..",
+            "m/44'/60'/0'/0/-1",
+            "0..5",
+            "",
+        ] {
+            assert!(parse_derivation_range(path).is_err(), "accepted {:?}", path);
+        }
+    }
+
+    #[test]
+    fn derives_the_known_test_mnemonic_addresses() {
+        let wallets =
+            derive_mnemonic_wallets(Some(TEST_MNEMONIC), "m/44'/60'/0'/0/0..1", "hd").unwrap();
+
+        let derived: Vec<(&str, H160)> = wallets
+            .iter()
+            .map(|(name, wallet)| (name.as_str(), wallet.address()))
+            .collect();
+        assert_eq!(
+            derived,
+            [
+                (
+                    "hd0",
+                    "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+                        .parse()
+                        .unwrap()
+                ),
+                (
+                    "hd1",
+                    "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+                        .parse()
+                        .unwrap()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use ethers::prelude::*;
+use ethers::types::{H160, H256};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How transaction fees are priced on a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasModel {
+    /// Base fee plus priority fee, from `eth_feeHistory`.
+    Eip1559,
+    /// A single `eth_gasPrice`, used as both the max fee and the priority
+    /// fee.
+    Legacy,
+}
+
+/// A Uniswap V2 deployment or one of its forks. The fork must keep the V2
+/// router ABI and pair math; only the fee may differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V2Deployment {
+    pub router: H160,
+    pub factory: H160,
+    /// Hash of the pair creation code, used to derive pair addresses.
+    pub init_code_hash: H256,
+    /// Swap fee in basis points: 30 on Uniswap and Sushiswap, 25 on
+    /// PancakeSwap.
+    pub fee_bps: u64,
+}
+
+/// Uniswap V3 deployment on a chain.
+#[derive(Debug, Clone, Copy)]
+pub struct V3Deployment {
+    pub swap_router_02: H160,
+    pub quoter_v2: H160,
+    pub factory: H160,
+}
+
+/// Everything the bot needs to know about a chain.
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub name: &'static str,
+    /// Default V2 DEX, replaced by the `dex` setting when present.
+    pub v2: V2Deployment,
+    pub wrapped_native: H160,
+    /// Whether a Flashbots-compatible relay accepts bundles on this chain.
+    pub private_relay: bool,
+    pub gas_model: GasModel,
+    pub v3: Option<V3Deployment>,
+    /// Liquid tokens tried as the middle hop of a V2 route.
+    pub route_intermediates: Vec<H160>,
+}
+
+fn address(address: &str) -> H160 {
+    H160::from_str(address).expect("Failed to create chain registry address from string")
+}
+
+fn hash(hash: &str) -> H256 {
+    H256::from_str(hash).expect("Failed to create chain registry hash from string")
+}
+
+lazy_static::lazy_static! {
+    /// Chains the bot knows how to trade on, keyed by chain id.
+    pub static ref CHAINS: HashMap<u64, ChainConfig> = [
+        ChainConfig {
+            chain_id: 1,
+            name: "Ethereum",
+            v2: V2Deployment {
+                router: address("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"),
+                factory: address("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+                init_code_hash: hash("0x96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f"),
+                fee_bps: 30,
+            },
+            wrapped_native: address("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+            private_relay: true,
+            gas_model: GasModel::Eip1559,
+            v3: Some(V3Deployment {
+                swap_router_02: address("0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"),
+                quoter_v2: address("0x61fFE014bA17989E743c5F6cB21bF9697530B21e"),
+                factory: address("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+            }),
+            // USDC, USDT, DAI, WBTC
+            route_intermediates: vec![
+                address("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+                address("0xdAC17F958D2ee523a2206206994597C13D831ec7"),
+                address("0x6B175474E89094C44Da98b954EedeAC495271d0F"),
+                address("0x2260FAC5E5542a773Aa44fBCfeDF7C193bc2C599"),
+            ],
+        },
+        ChainConfig {
+            chain_id: 8453,
+            name: "Base",
+            v2: V2Deployment {
+                router: address("0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24"),
+                factory: address("0x8909Dc15e40173Ff4699343b6eB8132c65e18eC6"),
+                init_code_hash: hash("0x96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f"),
+                fee_bps: 30,
+            },
+            wrapped_native: address("0x4200000000000000000000000000000000000006"),
+            private_relay: false,
+            gas_model: GasModel::Eip1559,
+            v3: Some(V3Deployment {
+                swap_router_02: address("0x2626664c2603336E57B271c5C0b26F421741e481"),
+                quoter_v2: address("0x3d4e44Eb1374240CE5F1B871ab261CD16335B76a"),
+                factory: address("0x33128a8fC17869897dcE68Ed026d694621f6FDfD"),
+            }),
+            // USDC, USDbC, DAI
+            route_intermediates: vec![
+                address("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"),
+                address("0xd9aAEc86B65D86f6A7B5B1b0c42FFA531710b6CA"),
+                address("0x50c5725949A6F0c72E6C4a641F24049A917DB0Cb"),
+            ],
+        },
+        ChainConfig {
+            chain_id: 42161,
+            name: "Arbitrum One",
+            v2: V2Deployment {
+                router: address("0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24"),
+                factory: address("0xf1D7CC64Fb4452F05c498126312eBE29f30Fbcf9"),
+                init_code_hash: hash("0x96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f"),
+                fee_bps: 30,
+            },
+            wrapped_native: address("0x82aF49447D8a07e3bd95BD0d56f35241523fBab1"),
+            private_relay: false,
+            gas_model: GasModel::Eip1559,
+            v3: Some(V3Deployment {
+                swap_router_02: address("0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"),
+                quoter_v2: address("0x61fFE014bA17989E743c5F6cB21bF9697530B21e"),
+                factory: address("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+            }),
+            // USDC, USDT, DAI, WBTC
+            route_intermediates: vec![
+                address("0xaf88d065e77c8cC2239327C5EDb3A432268e5831"),
+                address("0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9"),
+                address("0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1"),
+                address("0x2f2a2543B76A4166549F7aaB2e75Bef0aefC5B0f"),
+            ],
+        },
+        ChainConfig {
+            chain_id: 56,
+            name: "BNB Smart Chain (PancakeSwap V2)",
+            v2: V2Deployment {
+                router: address("0x10ED43C718714eb63d5aA57B78B54704E256024E"),
+                factory: address("0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73"),
+                init_code_hash: hash("0x00fb7f630766e6a796048ea87d01acd3068e8ff67d078148a3fa3f4a84f69bd5"),
+                fee_bps: 25,
+            },
+            wrapped_native: address("0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c"),
+            private_relay: false,
+            gas_model: GasModel::Legacy,
+            v3: Some(V3Deployment {
+                swap_router_02: address("0xB971eF87ede563556b2ED4b1C0b0019111Dd85d2"),
+                quoter_v2: address("0x78D78E420Da98ad378D7799bE8f4AF69033EB077"),
+                factory: address("0xdB1d10011AD0Ff90774D0C6Bb92e5C5c8b4461F7"),
+            }),
+            // USDT, USDC, BUSD, BTCB
+            route_intermediates: vec![
+                address("0x55d398326f99059fF775485246999027B3197955"),
+                address("0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d"),
+                address("0xe9e7CEA3DedcA5984780Bafc599bD69ADd087D56"),
+                address("0x7130d2A12B9BCbFAe4f2634d864A1Ee1Ce3Ead9c"),
+            ],
+        },
+    ]
+    .into_iter()
+    .map(|chain| (chain.chain_id, chain))
+    .collect();
+}
+
+/// Looks up a chain in the registry.
+pub fn chain_config(chain_id: u64) -> Option<&'static ChainConfig> {
+    CHAINS.get(&chain_id)
+}
+
+/// Reads `eth_chainId` and returns the matching registry entry, with its V2
+/// DEX replaced by `dex` when given. Contract addresses and the gas model
+/// are read from the returned config, which is passed to everything that
+/// talks to this provider.
+pub async fn detect_chain(
+    provider: &Arc<Provider<Http>>,
+    dex: Option<V2Deployment>,
+) -> eyre::Result<ChainConfig> {
+    let chain_id = provider
+        .get_chainid()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get the chain id: {}", e))?
+        .as_u64();
+
+    let mut config = chain_config(chain_id)
+        .ok_or_else(|| eyre::eyre!("Chain {} is not in the chain registry", chain_id))?
+        .clone();
+    if let Some(dex) = dex {
+        config.v2 = dex;
+    }
+
+    println!(
+        "Connected to {} (chain id {})",
+        config.name, config.chain_id
+    );
+    Ok(config)
+}
@@ -3,18 +3,20 @@ use ethers::{
     prelude::{NonceManagerMiddleware, SignerMiddleware},
     providers::{Http, Provider, Ws},
     signers::{LocalWallet, Signer},
-    types::H160,
+    types::{H160, U256},
+    utils::{get_create2_address_from_hash, keccak256, parse_units},
 };
 use ethers_flashbots::{BroadcasterMiddleware, FlashbotsMiddleware};
 use regex::Regex;
 use std::fs;
 use std::result::Result;
-use std::str::FromStr;
 use std::{
     sync::Arc,
     time::{Duration, SystemTime},
 };
 use url::Url;
+
+use crate::core::chain::V2Deployment;
 pub fn deadline_timestamp() -> u64 {
     let deadline = SystemTime::now() + Duration::from_secs(60 * 1); // 3 minutes from now
     deadline
@@ -36,23 +38,77 @@ impl std::error::Error for CustomError {}
 pub type StandardMiddlewareProvider =
     SignerMiddleware<Arc<NonceManagerMiddleware<Arc<Provider<Http>>>>, LocalWallet>;
 
-pub type BroadcasterMiddlewareProvider = SignerMiddleware<
-    BroadcasterMiddleware<Arc<ethers_providers::Provider<ethers_providers::Http>>, LocalWallet>,
-    LocalWallet,
->;
+pub type BroadcasterMiddlewareProvider =
+    BroadcasterMiddleware<Arc<ethers_providers::Provider<ethers_providers::Http>>, LocalWallet>;
+
+/// Builder URL alongside the middleware that submits bundles only to it.
+pub type BuilderClients = Vec<(Url, BroadcasterMiddlewareProvider)>;
 
-pub type FlashbotsMiddlewareProvider = SignerMiddleware<
-    FlashbotsMiddleware<Arc<ethers_providers::Provider<ethers_providers::Http>>, LocalWallet>,
-    LocalWallet,
->;
+pub type FlashbotsMiddlewareProvider =
+    FlashbotsMiddleware<Arc<ethers_providers::Provider<ethers_providers::Http>>, LocalWallet>;
+
+/// Bundle submission clients, built once at startup and shared by every
+/// round. They authenticate with the bundle signer only; transactions are
+/// signed by the trading wallets themselves.
+pub type BundleClient = (BuilderClients, FlashbotsMiddlewareProvider);
 
 pub const _ZERO_ADDRESS: [u8; 20] = [0u8; 20];
 
-lazy_static::lazy_static! {
-    pub static ref UNISWAP_V2_ROUTER: H160 = H160::from_str("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D").expect("Failed to create v2 router address from string");
-    pub static ref WETH_ADDRESS: H160 = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").expect("Failed to create weth address from string");
+/// Default gas limit for a swap whose gas estimate reverts, see
+/// `GasLimitPolicy`. Swaps are otherwise sent with their padded estimate.
+pub const SWAP_GAS_FALLBACK: u64 = 313252;
+
+pub const FLASHBOTS_RELAY_URL: &str = "https://relay.flashbots.net";
+
+/// Default builder set, used when `settings.json` has no `flashbots.builders`.
+pub const BUILDER_URLS: [&str; 15] = [
+    "https://builder0x69.io",
+    "https://rpc.beaverbuild.org",
+    "https://relay.flashbots.net",
+    "https://rsync-builder.xyz",
+    "https://api.blocknative.com/v1/auction",
+    "https://builder.gmbit.co/rpc",
+    "https://eth-builder.com",
+    "https://rpc.titanbuilder.xyz",
+    "https://buildai.net",
+    "https://rpc.payload.de",
+    "https://mev.api.blxrbdn.com",
+    "https://rpc.lightspeedbuilder.info",
+    "https://rpc.nfactorial.xyz",
+    "https://boba-builder.com/searcher",
+    "https://rpc.f1b.io",
+];
+
+/// Validated relay and builder endpoints for bundle submission.
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// Relay used for `eth_callBundle` simulations.
+    pub relay_url: Url,
+    /// Enabled builders only.
+    pub builders: Vec<BuilderConfig>,
+    /// Flashbots reputation key that signs every bundle, unless a builder
+    /// has its own signer.
+    pub bundle_signer: Option<LocalWallet>,
+}
 
+#[derive(Debug, Clone)]
+pub struct BuilderConfig {
+    pub url: Url,
+    /// Signs the `X-Flashbots-Signature` header for this builder instead of
+    /// the shared bundle signer.
+    pub signer: Option<LocalWallet>,
+}
 
+/// How much of a wallet's `tokenToBuy` balance a sell should spend.
+#[derive(Debug, Clone, Copy)]
+pub enum SellAmount {
+    /// Fraction of the current balance, `1.0` sells everything.
+    Percentage(f64),
+    /// Fixed amount in whole tokens, scaled by the token's `decimals()`.
+    Tokens(f64),
+}
+
+lazy_static::lazy_static! {
     pub static ref UNISWAP_V2_ROUTER_02: String = fs::read_to_string("./abi/uniswapV2Router02_ABI.json")
         .expect("Unable to read Uniswap V2 ABI file");
 
@@ -65,12 +121,33 @@ lazy_static::lazy_static! {
         .expect("Unable to read Uniswap V2 ABI file");
 
 
+        pub static ref UNISWAP_V2_FACTORY_ABI: String = fs::read_to_string("./abi/v2Factory.json")
+        .expect("Unable to read Uniswap V2 Factory ABI file");
+
+
         pub static ref ERC20: String = fs::read_to_string("./abi/ERC20_ABI.json")
         .expect("Unable to read ERC20 ABI file");
 
 
+        pub static ref UNISWAP_V3_SWAP_ROUTER_02_ABI: String = fs::read_to_string("./abi/swapRouter02_ABI.json")
+        .expect("Unable to read Uniswap V3 SwapRouter02 ABI file");
+
+
+        pub static ref UNISWAP_V3_QUOTER_V2_ABI: String = fs::read_to_string("./abi/quoterV2_ABI.json")
+        .expect("Unable to read Uniswap V3 QuoterV2 ABI file");
+
+
+        pub static ref UNISWAP_V3_FACTORY_ABI: String = fs::read_to_string("./abi/v3Factory.json")
+        .expect("Unable to read Uniswap V3 Factory ABI file");
+
+
+        pub static ref UNISWAP_V3_POOL_ABI: String = fs::read_to_string("./abi/v3Pool.json")
+        .expect("Unable to read Uniswap V3 Pool ABI file");
+
+
 }
 pub async fn load_uniswap_v2_mempool(
+    router: H160,
     wallet: &LocalWallet,
     provider: Arc<Provider<Http>>,
 ) -> Result<ConfigContractmempool, Box<dyn std::error::Error + Send>> {
@@ -82,15 +159,118 @@ pub async fn load_uniswap_v2_mempool(
             ))) as Box<dyn std::error::Error + Send>
         })?;
 
-    let v2_router_address =
-        H160::from_str("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D").map_err(|e| {
-            Box::new(CustomError(format!(
-                "Failed to create v2 router address from string: {}",
-                e
-            ))) as Box<dyn std::error::Error + Send>
-        })?;
+    uniswap_v2_router_mempool(wallet, router, v2_router_contract_abi, provider).await
+}
+
+pub async fn load_erc20_contract(
+    token_address: H160,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+) -> Result<ConfigContractmempool, Box<dyn std::error::Error + Send>> {
+    let erc20_contract_abi = ethabi::Contract::load(ERC20.as_bytes()).map_err(|e| {
+        Box::new(CustomError(format!(
+            "Failed to load ERC20 contract ABI: {}",
+            e
+        ))) as Box<dyn std::error::Error + Send>
+    })?;
+
+    let erc20_contract = Contract::new(
+        token_address,
+        erc20_contract_abi,
+        SignerMiddleware::new(provider, wallet.clone()).into(),
+    );
+
+    Ok(erc20_contract)
+}
+
+/// Resolves a [`SellAmount`] against `owner`'s current token balance.
+/// The result never exceeds the balance.
+pub async fn resolve_sell_amount(
+    erc20_contract: &ConfigContractmempool,
+    owner: H160,
+    sell_amount: SellAmount,
+) -> eyre::Result<U256> {
+    let balance = erc20_contract
+        .method::<_, U256>("balanceOf", owner)
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get token balance: {}", e))?;
+
+    let amount = match sell_amount {
+        SellAmount::Percentage(percentage) => {
+            let multiplier = U256::from((percentage * 1e18) as u128);
+            balance
+                .checked_mul(multiplier)
+                .and_then(|result| result.checked_div(U256::from(1_000_000_000_000_000_000u128)))
+                .ok_or_else(|| eyre::eyre!("Overflow occurred during sell amount calculation"))?
+        }
+        SellAmount::Tokens(tokens) => {
+            let decimals = erc20_contract
+                .method::<_, u8>("decimals", ())
+                .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+                .call()
+                .await
+                .map_err(|e| eyre::eyre!("Failed to get token decimals: {}", e))?;
+
+            if !tokens.is_finite() || tokens < 0.0 {
+                return Err(eyre::eyre!("Invalid token amount to sell: {}", tokens));
+            }
+
+            // Scale the decimal string, a float product loses precision at 18 decimals
+            parse_units(tokens.to_string(), decimals as u32)
+                .map_err(|e| eyre::eyre!("Invalid token amount to sell {}: {}", tokens, e))?
+                .into()
+        }
+    };
+
+    Ok(std::cmp::min(amount, balance))
+}
+
+/// Derives the `dex` pair address for two tokens with CREATE2, without any
+/// RPC call. Works before the pair exists.
+pub fn pair_for(dex: &V2Deployment, token_a: H160, token_b: H160) -> H160 {
+    let (token0, token1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+
+    let salt = keccak256([token0.as_bytes(), token1.as_bytes()].concat());
+    get_create2_address_from_hash(dex.factory, salt, dex.init_code_hash)
+}
+
+/// Returns the pair `factory` holds for two tokens, or the zero address when
+/// there is none.
+pub async fn load_pair_address_between(
+    token_a: H160,
+    token_b: H160,
+    factory: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<H160> {
+    let factory_abi = ethabi::Contract::load(UNISWAP_V2_FACTORY_ABI.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v2 factory contract ABI: {}", e))?;
+    let factory = Contract::new(factory, factory_abi, provider);
+
+    factory
+        .method::<_, H160>("getPair", (token_a, token_b))
+        .map_err(|_| eyre::eyre!("Uniswap V2 Factory contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get pair address: {}", e))
+}
 
-    uniswap_v2_router_mempool(wallet, v2_router_address, v2_router_contract_abi, provider).await
+/// Lowers `amount` by `slippage_percentage` (a fraction, `0.01` is 1%).
+pub fn apply_slippage(amount: U256, slippage_percentage: f64) -> eyre::Result<U256> {
+    let slippage_multiplier = U256::from((slippage_percentage * 1e18) as u128);
+    let slippage_adjustment = amount
+        .checked_mul(slippage_multiplier)
+        .and_then(|result| result.checked_div(U256::from(1_000_000_000_000_000_000u128)))
+        .ok_or_else(|| eyre::eyre!("Overflow occurred during slippage adjustment calculation"))?;
+
+    amount
+        .checked_sub(slippage_adjustment)
+        .ok_or_else(|| eyre::eyre!("Overflow occurred during amount out tokens calculation"))
 }
 
 async fn uniswap_v2_router_mempool(
@@ -119,13 +299,10 @@ pub async fn load_client_middleware(
 
 pub async fn load_flashbots_client_middleware(
     bundle_signer: &LocalWallet,
-    wallet: &LocalWallet,
     provider: Arc<Provider<Http>>,
-) -> Result<
-    (BroadcasterMiddlewareProvider, FlashbotsMiddlewareProvider),
-    Box<dyn std::error::Error + Send>,
-> {
-    create_flashbots_client_middleware(bundle_signer, wallet, provider).await
+    relay_config: &RelayConfig,
+) -> Result<BundleClient, Box<dyn std::error::Error + Send>> {
+    create_flashbots_client_middleware(bundle_signer, provider, relay_config).await
 }
 
 async fn create_client_middleware(
@@ -141,55 +318,35 @@ async fn create_client_middleware(
 
 async fn create_flashbots_client_middleware(
     bundle_signer: &LocalWallet,
-    wallet: &LocalWallet,
     provider: Arc<Provider<Http>>,
-) -> Result<
-    (BroadcasterMiddlewareProvider, FlashbotsMiddlewareProvider),
-    Box<dyn std::error::Error + Send>,
-> {
-    let builders = vec![
-        parse_url("https://builder0x69.io")?,
-        parse_url("https://rpc.beaverbuild.org")?,
-        parse_url("https://relay.flashbots.net")?,
-        parse_url("https://rsync-builder.xyz")?,
-        parse_url("https://api.blocknative.com/v1/auction")?,
-        parse_url("https://builder.gmbit.co/rpc")?,
-        parse_url("https://eth-builder.com")?,
-        parse_url("https://rpc.titanbuilder.xyz")?,
-        parse_url("https://buildai.net")?,
-        parse_url("https://rpc.payload.de")?,
-        parse_url("https://mev.api.blxrbdn.com")?,
-        parse_url("https://rpc.lightspeedbuilder.info")?,
-        parse_url("https://rpc.nfactorial.xyz")?,
-        parse_url("https://boba-builder.com/searcher")?,
-        parse_url("https://rpc.f1b.io")?,
-    ];
-
-    let relay_url = Url::parse("https://relay.flashbots.net").map_err(|e| {
-        Box::new(CustomError(format!("Failed to parse URL: {}", e)))
-            as Box<dyn std::error::Error + Send>
-    })?;
-
-    let _client = Arc::new(NonceManagerMiddleware::new(
-        provider.clone(),
-        wallet.address(),
-    ));
-
-    let flashbots_middleware = SignerMiddleware::new(
-        FlashbotsMiddleware::new(provider.clone(), relay_url.clone(), bundle_signer.clone()),
-        wallet.clone(),
-    );
-
-    let broadcaster =
-        BroadcasterMiddleware::new(provider, builders, relay_url.clone(), bundle_signer.clone());
-
-    // Combine with SignerMiddleware
-    let client_middleware = SignerMiddleware::new(broadcaster, wallet.clone());
+    relay_config: &RelayConfig,
+) -> Result<BundleClient, Box<dyn std::error::Error + Send>> {
+    let relay_url = relay_config.relay_url.clone();
+
+    let flashbots_middleware =
+        FlashbotsMiddleware::new(provider.clone(), relay_url.clone(), bundle_signer.clone());
+
+    // One broadcaster per builder, so every submission can be timed and
+    // reported per builder
+    let mut builders = Vec::new();
+    for builder in relay_config.builders.iter() {
+        let broadcaster = BroadcasterMiddleware::new(
+            provider.clone(),
+            vec![builder.url.clone()],
+            relay_url.clone(),
+            builder
+                .signer
+                .clone()
+                .unwrap_or_else(|| bundle_signer.clone()),
+        );
+
+        builders.push((builder.url.clone(), broadcaster));
+    }
 
-    Ok((client_middleware, flashbots_middleware))
+    Ok((builders, flashbots_middleware))
 }
 
-fn parse_url(url_str: &str) -> Result<Url, Box<dyn std::error::Error + Send>> {
+pub fn parse_url(url_str: &str) -> Result<Url, Box<dyn std::error::Error + Send>> {
     Url::parse(url_str).map_err(|e| {
         Box::new(CustomError(format!("Failed to parse URL: {}", e)))
             as Box<dyn std::error::Error + Send>
@@ -0,0 +1,352 @@
+use ethabi::{ParamType, Token};
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Bytes, H160, U256};
+use ethers::utils::{format_ether, format_units, keccak256};
+use ethers_core::types::Eip1559TransactionRequest;
+use ethers_flashbots::SimulatedTransaction;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::core::contracts::ERC20;
+use crate::core::private_txn::{sign_wallet_transaction, BundleTransactionSource};
+use crate::core::safety::{decode_uint, PROBE_READ_GAS_LIMIT};
+
+/// `Error(string)` selector solidity prefixes revert messages with.
+const REVERT_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// What one transaction of a dry run is meant to do, so its simulated result
+/// can be reported in token terms.
+#[derive(Debug, Clone)]
+pub enum DryRunStep {
+    /// An approval, or a transaction that is not ours.
+    Other(&'static str),
+    /// Spends `amount_in` wei on `token`. `expected_out` is our own quote,
+    /// reported when neither balance probes nor the call's return data give
+    /// the output.
+    Buy {
+        token: H160,
+        amount_in: U256,
+        expected_out: Option<U256>,
+    },
+    /// Sells `amount_in` of `token` for ETH.
+    Sell {
+        token: H160,
+        amount_in: U256,
+        expected_out: Option<U256>,
+    },
+}
+
+/// Outcome of simulating one transaction.
+#[derive(Debug, Clone)]
+pub struct DryRunResult {
+    pub gas_used: U256,
+    pub return_data: Option<Bytes>,
+    pub revert: Option<String>,
+    /// How much the wallet's token balance grew, from the `balanceOf` probes
+    /// around a buy in a simulated bundle.
+    pub balance_delta: Option<U256>,
+}
+
+/// Where one step's transaction, and the `balanceOf` probes around it, sit
+/// in a dry-run bundle.
+#[derive(Debug, Clone, Copy)]
+pub struct BundleStep {
+    pub tx: usize,
+    pub balance_probes: Option<(usize, usize)>,
+}
+
+/// Signs each request exactly as it would be sent and runs it through
+/// `eth_call` and `eth_estimateGas` against the latest block. Nothing is
+/// broadcast.
+///
+/// `eth_call` runs every transaction on its own, so a swap listed after an
+/// approval it depends on is simulated without that approval.
+pub async fn dry_run_transactions(
+    transactions: Vec<(DryRunStep, Eip1559TransactionRequest)>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<()> {
+    let needs_approval = transactions
+        .iter()
+        .any(|(step, _)| matches!(step, DryRunStep::Other("approve")));
+
+    for (step, request) in transactions {
+        let raw_tx = sign_wallet_transaction(wallet, request.clone()).await?;
+        println!(
+            "Dry run: signed {:?} ({} bytes), not broadcast",
+            H256::from(keccak256(&raw_tx)),
+            raw_tx.len()
+        );
+
+        let tx = TypedTransaction::Eip1559(request.chain_id(wallet.chain_id()));
+        let result = match provider.call(&tx, None).await {
+            Ok(return_data) => DryRunResult {
+                gas_used: provider
+                    .estimate_gas(&tx, None)
+                    .await
+                    .map_err(|e| eyre::eyre!("Failed to estimate gas: {}", e))?,
+                return_data: Some(return_data),
+                revert: None,
+                balance_delta: None,
+            },
+            Err(e) => DryRunResult {
+                gas_used: U256::zero(),
+                return_data: None,
+                revert: Some(provider_revert_reason(&e)),
+                balance_delta: None,
+            },
+        };
+
+        if needs_approval && result.revert.is_some() && !matches!(step, DryRunStep::Other(_)) {
+            println!("Dry run: eth_call does not apply the approval before this swap");
+        }
+
+        print_result(&step, &result, provider.clone()).await?;
+    }
+
+    Ok(())
+}
+
+/// Brackets every buy in `transactions` with `balanceOf` probes of the
+/// bought token for the buying wallet, the way the token safety check does,
+/// so a dry run reports the tokens that actually arrived. Later transactions
+/// of the same wallet have their nonces moved up to make room. `steps` lines
+/// up with `transactions`; the returned layout lines up with `steps`.
+pub fn with_balance_probes(
+    transactions: &[BundleTransactionSource],
+    steps: &[DryRunStep],
+) -> eyre::Result<(Vec<BundleTransactionSource>, Vec<BundleStep>)> {
+    let erc20_abi = ethabi::Contract::load(ERC20.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load ERC20 contract ABI: {}", e))?;
+    let balance_of = erc20_abi
+        .function("balanceOf")
+        .map_err(|e| eyre::eyre!("ERC20 contract method not found: {}", e))?;
+
+    let mut probed = Vec::with_capacity(transactions.len());
+    let mut layout = Vec::with_capacity(steps.len());
+    // Probes added so far per wallet, which its later nonces move up by
+    let mut shifts: HashMap<H160, U256> = HashMap::new();
+
+    for (transaction, step) in transactions.iter().zip(steps.iter()) {
+        let (wallet, request) = match transaction {
+            BundleTransactionSource::Own { wallet, request } => (wallet, request),
+            BundleTransactionSource::Foreign(_) => {
+                layout.push(BundleStep {
+                    tx: probed.len(),
+                    balance_probes: None,
+                });
+                probed.push(transaction.clone());
+                continue;
+            }
+        };
+
+        let shift = shifts.entry(wallet.address()).or_default();
+        let own = |request: Eip1559TransactionRequest| BundleTransactionSource::Own {
+            wallet: wallet.clone(),
+            request: Box::new(request),
+        };
+
+        let (token, nonce) = match (step, request.nonce) {
+            (DryRunStep::Buy { token, .. }, Some(nonce)) => (*token, nonce + *shift),
+            (_, nonce) => {
+                let request = request.as_ref().clone();
+                let request = match nonce {
+                    Some(nonce) => request.nonce(nonce + *shift),
+                    None => request,
+                };
+                layout.push(BundleStep {
+                    tx: probed.len(),
+                    balance_probes: None,
+                });
+                probed.push(own(request));
+                continue;
+            }
+        };
+
+        let balance_of_data: Bytes = balance_of
+            .encode_input(&[Token::Address(wallet.address())])
+            .map_err(|e| eyre::eyre!("Failed to encode balanceOf: {}", e))?
+            .into();
+        let probe = |nonce: U256| {
+            Eip1559TransactionRequest::new()
+                .to(token)
+                .data(balance_of_data.clone())
+                .gas(PROBE_READ_GAS_LIMIT)
+                .nonce(nonce)
+        };
+
+        let before = probed.len();
+        probed.push(own(probe(nonce)));
+        probed.push(own(request.as_ref().clone().nonce(nonce + 1)));
+        probed.push(own(probe(nonce + 2)));
+        layout.push(BundleStep {
+            tx: before + 1,
+            balance_probes: Some((before, before + 2)),
+        });
+        *shift += U256::from(2);
+    }
+
+    Ok((probed, layout))
+}
+
+/// Reports every step of a simulated bundle, laid out by
+/// `with_balance_probes`.
+pub async fn report_simulated_bundle(
+    steps: &[DryRunStep],
+    layout: &[BundleStep],
+    simulated: &[SimulatedTransaction],
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<()> {
+    for (step, placement) in steps.iter().zip(layout.iter()) {
+        let simulated_tx = match simulated.get(placement.tx) {
+            Some(simulated_tx) => simulated_tx,
+            None => break,
+        };
+
+        let balance_delta = placement.balance_probes.and_then(|(before, after)| {
+            let before = decode_uint(simulated.get(before)?).ok()?;
+            let after = decode_uint(simulated.get(after)?).ok()?;
+            after.checked_sub(before)
+        });
+
+        let result = DryRunResult {
+            gas_used: simulated_tx.gas_used,
+            return_data: simulated_tx.value.clone(),
+            revert: simulated_tx
+                .error
+                .as_ref()
+                .or(simulated_tx.revert.as_ref())
+                .cloned(),
+            balance_delta,
+        };
+
+        print_result(step, &result, provider.clone()).await?;
+    }
+
+    Ok(())
+}
+
+async fn print_result(
+    step: &DryRunStep,
+    result: &DryRunResult,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<()> {
+    if let Some(revert) = &result.revert {
+        println!("Dry run {}: reverted: {}", step_name(step), revert);
+        return Ok(());
+    }
+
+    let (token, amount_in, expected_out, is_buy) = match step {
+        DryRunStep::Other(name) => {
+            println!("Dry run {}: ok, gas used {}", name, result.gas_used);
+            return Ok(());
+        }
+        DryRunStep::Buy {
+            token,
+            amount_in,
+            expected_out,
+        } => (*token, *amount_in, *expected_out, true),
+        DryRunStep::Sell {
+            token,
+            amount_in,
+            expected_out,
+        } => (*token, *amount_in, *expected_out, false),
+    };
+
+    // Balance probes give what actually arrived after any transfer tax; V3
+    // multicalls return the swap output; V2 fee-on-transfer swaps outside a
+    // bundle return nothing, so fall back to the quote
+    let simulated_out = result
+        .balance_delta
+        .or_else(|| result.return_data.as_ref().and_then(decode_amount_out));
+    let (amount_out, source) = match simulated_out {
+        Some(amount_out) => (amount_out, "simulated"),
+        None => (expected_out.unwrap_or_default(), "quoted"),
+    };
+
+    let decimals = token_decimals(token, provider).await?;
+    let (eth, tokens) = match is_buy {
+        true => (amount_in, amount_out),
+        false => (amount_out, amount_in),
+    };
+    let eth_amount: f64 = format_ether(eth).parse().unwrap_or_default();
+    let token_amount: f64 = format_units(tokens, decimals as u32)
+        .map_err(|e| eyre::eyre!("Failed to format token amount: {}", e))?
+        .parse()
+        .unwrap_or_default();
+
+    let price = if token_amount > 0.0 {
+        format!("{:.12} ETH per token", eth_amount / token_amount)
+    } else {
+        "n/a".to_string()
+    };
+
+    match is_buy {
+        true => println!(
+            "Dry run buy: {} ETH -> {} tokens ({}), gas used {}, effective price {}",
+            eth_amount, token_amount, source, result.gas_used, price
+        ),
+        false => println!(
+            "Dry run sell: {} tokens -> {} ETH ({}), gas used {}, effective price {}",
+            token_amount, eth_amount, source, result.gas_used, price
+        ),
+    }
+
+    Ok(())
+}
+
+fn step_name(step: &DryRunStep) -> &'static str {
+    match step {
+        DryRunStep::Other(name) => name,
+        DryRunStep::Buy { .. } => "buy",
+        DryRunStep::Sell { .. } => "sell",
+    }
+}
+
+/// The swap output from a SwapRouter02 `multicall` return value, whose
+/// first result is the `amountOut` of `exactInput(Single)`.
+fn decode_amount_out(return_data: &Bytes) -> Option<U256> {
+    let results =
+        ethabi::decode(&[ParamType::Array(Box::new(ParamType::Bytes))], return_data).ok()?;
+
+    let first = results
+        .into_iter()
+        .next()?
+        .into_array()?
+        .into_iter()
+        .next()?
+        .into_bytes()?;
+
+    (first.len() >= 32).then(|| U256::from_big_endian(&first[..32]))
+}
+
+/// The revert message carried by a failed `eth_call`, or the error itself.
+fn provider_revert_reason(error: &ProviderError) -> String {
+    let revert_data =
+        RpcError::as_error_response(error).and_then(|response| response.as_revert_data());
+
+    match revert_data {
+        Some(data) if data.len() >= 4 && data[..4] == REVERT_SELECTOR => {
+            ethabi::decode(&[ParamType::String], &data[4..])
+                .ok()
+                .and_then(|tokens| tokens.into_iter().next())
+                .and_then(|token| token.into_string())
+                .unwrap_or_else(|| format!("{:?}", data))
+        }
+        Some(data) => format!("{:?}", data),
+        None => error.to_string(),
+    }
+}
+
+async fn token_decimals(token: H160, provider: Arc<Provider<Http>>) -> eyre::Result<u8> {
+    let erc20_abi = ethabi::Contract::load(ERC20.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load ERC20 contract ABI: {}", e))?;
+
+    Contract::new(token, erc20_abi, provider)
+        .method::<_, u8>("decimals", ())
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get token decimals: {}", e))
+}
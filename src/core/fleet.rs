@@ -0,0 +1,272 @@
+use ethers::prelude::*;
+use ethers::types::{H160, H256, U256};
+use ethers_core::types::Eip1559TransactionRequest;
+use spinners::{Spinner, Spinners};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::join;
+
+use crate::core::contracts::{load_client_middleware, load_erc20_contract};
+use crate::core::dry_run::{dry_run_transactions, DryRunStep};
+use crate::core::gas::{estimate_fees, SendSettings};
+use crate::core::private_txn::{
+    dry_run_bundle, submit_bundle_until_included, BundleTarget, BundleTransactionSource,
+};
+use crate::core::public_txn::wait_for_receipt;
+
+pub const ETH_TRANSFER_GAS_LIMIT: u64 = 21_000;
+pub const TOKEN_TRANSFER_GAS_LIMIT: u64 = 100_000;
+
+/// Tops every target up to its required balance from `treasury`.
+///
+/// `targets` holds `(name, address, required wei)`; wallets that already
+/// hold enough are skipped. With `bundle` set, all transfers go out as one
+/// Flashbots bundle, otherwise as regular transactions. With `send.dry_run`
+/// they are signed and simulated only. Transfers use fixed gas limits, so
+/// `send.gas_policy` is not consulted.
+pub async fn fund_wallets(
+    treasury: &LocalWallet,
+    targets: Vec<(String, H160, U256)>,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    bundle: Option<BundleTarget<'_>>,
+) -> eyre::Result<()> {
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_model,
+        ..
+    } = *send;
+
+    let (nonce_result, gas_details_result) = join!(
+        provider.get_transaction_count(treasury.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+    );
+
+    let mut nonce =
+        nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let mut transfers = Vec::new();
+
+    for (name, address, required) in targets {
+        let balance = provider
+            .get_balance(address, None)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get balance of {}: {}", name, e))?;
+
+        if balance >= required {
+            println!("{} ({:?}) already holds {} wei", name, address, balance);
+            continue;
+        }
+
+        let top_up = required - balance;
+        println!("Funding {} ({:?}) with {} wei", name, address, top_up);
+
+        transfers.push(
+            Eip1559TransactionRequest::new()
+                .from(treasury.address())
+                .to(address)
+                .value(top_up)
+                .gas(ETH_TRANSFER_GAS_LIMIT)
+                .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+                .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+                .nonce(nonce),
+        );
+
+        nonce += U256::one();
+    }
+
+    if transfers.is_empty() {
+        println!("All wallets are already funded");
+        return Ok(());
+    }
+
+    let transfers = transfers
+        .into_iter()
+        .map(|request| (treasury.clone(), request))
+        .collect();
+
+    send_transfers(transfers, provider, send, bundle).await
+}
+
+/// Moves every wallet's `token_address` balance and then its ETH, minus the
+/// gas the sweep itself needs, back to `treasury`.
+///
+/// In bundle mode twice the current max fee is held back for gas, so a
+/// re-submission with refreshed fees still fits in the wallet's balance.
+pub async fn sweep_wallets(
+    treasury: H160,
+    wallets: Vec<(String, LocalWallet)>,
+    token_address: &str,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    bundle: Option<BundleTarget<'_>>,
+) -> eyre::Result<()> {
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_model,
+        ..
+    } = *send;
+
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) = estimate_fees(provider.as_ref(), gas_model)
+        .await
+        .map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let gas_price = max_fee_per_gas + buy_extra_gas;
+    let fee_headroom = if bundle.is_some() { 2 } else { 1 };
+
+    let mut transfers = Vec::new();
+
+    for (name, wallet) in wallets {
+        let (nonce_result, balance_result, erc20_contract_result) = join!(
+            provider.get_transaction_count(wallet.address(), None),
+            provider.get_balance(wallet.address(), None),
+            load_erc20_contract(tokenaddress, &wallet, provider.clone()),
+        );
+
+        let mut nonce =
+            nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+        let eth_balance =
+            balance_result.map_err(|e| eyre::eyre!("Failed to get balance of {}: {}", name, e))?;
+        let erc20_contract = erc20_contract_result
+            .map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
+
+        let token_balance = erc20_contract
+            .method::<_, U256>("balanceOf", wallet.address())
+            .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+            .call()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get token balance: {}", e))?;
+
+        let mut gas_needed = U256::from(ETH_TRANSFER_GAS_LIMIT);
+
+        if !token_balance.is_zero() {
+            let transfer_data = erc20_contract
+                .method::<_, bool>("transfer", (treasury, token_balance))
+                .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+                .calldata()
+                .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+            println!("Sweeping {} tokens from {}", token_balance, name);
+
+            transfers.push((
+                wallet.clone(),
+                Eip1559TransactionRequest::new()
+                    .from(wallet.address())
+                    .to(tokenaddress)
+                    .gas(TOKEN_TRANSFER_GAS_LIMIT)
+                    .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+                    .max_fee_per_gas(gas_price)
+                    .data(transfer_data)
+                    .nonce(nonce),
+            ));
+
+            nonce += U256::one();
+            gas_needed += U256::from(TOKEN_TRANSFER_GAS_LIMIT);
+        }
+
+        let gas_reserve = gas_needed * gas_price * U256::from(fee_headroom);
+
+        match eth_balance.checked_sub(gas_reserve) {
+            Some(sweep_value) if !sweep_value.is_zero() => {
+                println!("Sweeping {} wei from {}", sweep_value, name);
+
+                transfers.push((
+                    wallet.clone(),
+                    Eip1559TransactionRequest::new()
+                        .from(wallet.address())
+                        .to(treasury)
+                        .value(sweep_value)
+                        .gas(ETH_TRANSFER_GAS_LIMIT)
+                        .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+                        .max_fee_per_gas(gas_price)
+                        .nonce(nonce),
+                ));
+            }
+            _ => println!("{} has no ETH left to sweep after gas", name),
+        }
+    }
+
+    if transfers.is_empty() {
+        println!("Nothing to sweep");
+        return Ok(());
+    }
+
+    send_transfers(transfers, provider, send, bundle).await
+}
+
+async fn send_transfers(
+    transfers: Vec<(LocalWallet, Eip1559TransactionRequest)>,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    bundle: Option<BundleTarget<'_>>,
+) -> eyre::Result<()> {
+    let dry_run = send.dry_run;
+
+    if let Some(target) = bundle {
+        let mut sp = Spinner::new(Spinners::Dots9, "Waiting for bundle inclusion...".into());
+
+        let transactions: Vec<_> = transfers
+            .into_iter()
+            .map(|(wallet, request)| BundleTransactionSource::Own {
+                wallet,
+                request: Box::new(request),
+            })
+            .collect();
+
+        if dry_run {
+            let steps = vec![DryRunStep::Other("transfer"); transactions.len()];
+            return dry_run_bundle(
+                target.client,
+                provider,
+                &transactions,
+                &steps,
+                send,
+                &mut sp,
+            )
+            .await;
+        }
+
+        return submit_bundle_until_included(target, &provider, None, transactions, send, &mut sp)
+            .await;
+    }
+
+    if dry_run {
+        for (wallet, request) in transfers {
+            dry_run_transactions(
+                vec![(DryRunStep::Other("transfer"), request)],
+                &wallet,
+                provider.clone(),
+            )
+            .await?;
+        }
+        return Ok(());
+    }
+
+    let mut pending_tx_hashes: Vec<H256> = Vec::new();
+
+    for (wallet, request) in transfers {
+        let client = load_client_middleware(&wallet, &wallet, provider.clone())
+            .await
+            .map_err(|e| eyre::eyre!("Failed to load client middleware: {}", e))?;
+
+        let pending_tx = client
+            .send_transaction(request, None)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to send transaction: {}", e))?;
+
+        pending_tx_hashes.push(pending_tx.tx_hash());
+    }
+
+    for tx_hash in pending_tx_hashes {
+        wait_for_receipt(&provider, tx_hash).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::U256;
+use ethers_core::types::Eip1559TransactionRequest;
+
+use crate::core::chain::GasModel;
+use crate::core::contracts::SWAP_GAS_FALLBACK;
+
+/// `(max_fee_per_gas, max_priority_fee_per_gas)` under the chain's
+/// `gas_model`. On legacy-priced chains both are the current `eth_gasPrice`.
+pub async fn estimate_fees<M: Middleware>(
+    client: &M,
+    gas_model: GasModel,
+) -> Result<(U256, U256), M::Error> {
+    match gas_model {
+        GasModel::Eip1559 => client.estimate_eip1559_fees(None).await,
+        GasModel::Legacy => {
+            let gas_price = client.get_gas_price().await?;
+            Ok((gas_price, gas_price))
+        }
+    }
+}
+
+/// How a swap's gas limit is derived from an estimate.
+#[derive(Debug, Clone, Copy)]
+pub struct GasLimitPolicy {
+    /// Safety multiplier applied to `estimate_gas` or a simulation's
+    /// `gas_used`.
+    pub multiplier: f64,
+    /// Hard ceiling for any gas limit.
+    pub cap: u64,
+    /// Gas limit used when estimation reverts.
+    pub fallback: u64,
+}
+
+impl Default for GasLimitPolicy {
+    fn default() -> Self {
+        GasLimitPolicy {
+            multiplier: 1.2,
+            cap: 1_000_000,
+            fallback: SWAP_GAS_FALLBACK,
+        }
+    }
+}
+
+impl GasLimitPolicy {
+    /// Pads `gas_used` by the multiplier and clamps it to the cap.
+    pub fn apply(&self, gas_used: U256) -> U256 {
+        let padded = gas_used * U256::from((self.multiplier * 1000.0) as u64) / U256::from(1000);
+        std::cmp::min(padded, U256::from(self.cap))
+    }
+
+    pub fn fallback(&self) -> U256 {
+        U256::from(std::cmp::min(self.fallback, self.cap))
+    }
+
+    /// Estimates `txn_request` with `estimate_gas`. When the estimate reverts
+    /// (e.g. the swap depends on an earlier transaction that is not mined
+    /// yet) the fallback limit is used instead.
+    pub async fn estimate<M: Middleware>(
+        &self,
+        client: &M,
+        txn_request: &Eip1559TransactionRequest,
+    ) -> U256 {
+        let tx = TypedTransaction::Eip1559(txn_request.clone());
+
+        match client.estimate_gas(&tx, None).await {
+            Ok(estimate) => self.apply(estimate),
+            Err(e) => {
+                println!(
+                    "Gas estimation failed, falling back to {}: {}",
+                    self.fallback(),
+                    e
+                );
+                self.fallback()
+            }
+        }
+    }
+}
+
+/// How every transaction of a run is priced and whether it is sent at all.
+#[derive(Debug, Clone, Copy)]
+pub struct SendSettings<'a> {
+    /// Added to the estimated max fee per gas.
+    pub buy_extra_gas: U256,
+    /// Added to the estimated priority fee.
+    pub miner_tip: U256,
+    pub gas_policy: &'a GasLimitPolicy,
+    /// How the chain prices gas, from its `ChainConfig`.
+    pub gas_model: GasModel,
+    /// Sign and simulate only, never broadcast.
+    pub dry_run: bool,
+}
@@ -1,3 +1,15 @@
+pub mod chain;
 pub mod contracts;
+pub mod dry_run;
+pub mod fleet;
+pub mod gas;
+pub mod pair;
+pub mod preflight;
 pub mod private_txn;
 pub mod public_txn;
+pub mod report;
+pub mod router;
+pub mod safety;
+pub mod slippage;
+pub mod v3;
+pub mod watcher;
@@ -0,0 +1,362 @@
+use ethers::prelude::*;
+use ethers::types::{Log, H160, U256, U64};
+use std::sync::Arc;
+use tokio::join;
+
+use crate::core::chain::V2Deployment;
+use crate::core::contracts::{load_pair_address_between, UNISWAP_V2_PAIR};
+
+/// Basis points in one.
+pub const BPS: u64 = 10_000;
+
+/// Uniswap V2 `getAmountOut` with a swap fee of `fee_bps` (30 on Uniswap).
+pub fn get_amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256, fee_bps: u64) -> U256 {
+    if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+        return U256::zero();
+    }
+
+    let amount_in_with_fee = amount_in * U256::from(BPS - fee_bps.min(BPS));
+    amount_in_with_fee * reserve_out / (reserve_in * U256::from(BPS) + amount_in_with_fee)
+}
+
+/// Uniswap V2 `getAmountIn` with a swap fee of `fee_bps`, rounded up like
+/// the router. `None` when the pair cannot pay out `amount_out`.
+pub fn get_amount_in(
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee_bps: u64,
+) -> Option<U256> {
+    if amount_out.is_zero() || reserve_in.is_zero() || amount_out >= reserve_out || fee_bps >= BPS {
+        return None;
+    }
+
+    let numerator = reserve_in * amount_out * U256::from(BPS);
+    let denominator = (reserve_out - amount_out) * U256::from(BPS - fee_bps);
+    Some(numerator / denominator + U256::one())
+}
+
+/// Fee-on-transfer taxes of a token, in basis points.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TransferTax {
+    /// Share of the pair's output that never reaches the buyer.
+    pub buy_bps: u64,
+    /// Share of the sold tokens that never reaches the pair.
+    pub sell_bps: u64,
+}
+
+impl TransferTax {
+    fn after_tax(amount: U256, bps: u64) -> U256 {
+        amount * U256::from(BPS - bps.min(BPS)) / U256::from(BPS)
+    }
+
+    /// Part of a swap's `amount_out` that reaches the buyer.
+    pub fn bought(&self, amount_out: U256) -> U256 {
+        Self::after_tax(amount_out, self.buy_bps)
+    }
+
+    /// Part of `amount_in` sold tokens that reaches the first pair.
+    pub fn sold(&self, amount_in: U256) -> U256 {
+        Self::after_tax(amount_in, self.sell_bps)
+    }
+}
+
+/// Local model of a token's WETH pair. Quotes are computed from the cached
+/// reserves without an RPC round-trip; the reserves are read once per
+/// `load`, and only `buy` moves them afterwards.
+#[derive(Debug, Clone)]
+pub struct UniswapV2Pair {
+    pub address: H160,
+    pub token: H160,
+    /// Wrapped native token the pair trades `token` against.
+    pub weth: H160,
+    pub reserve_weth: U256,
+    pub reserve_token: U256,
+    pub transfer_tax: TransferTax,
+    /// Swap fee of the DEX the pair belongs to, in basis points.
+    pub fee_bps: u64,
+    /// Block the reserves were last read or synced at.
+    pub synced_block: U64,
+}
+
+impl UniswapV2Pair {
+    /// Reads the address and reserves of `dex`'s `weth`/`token` pair. The
+    /// reserves are zero when the pair does not exist yet.
+    pub async fn load(
+        token: H160,
+        dex: &V2Deployment,
+        weth: H160,
+        provider: Arc<Provider<Http>>,
+    ) -> eyre::Result<Self> {
+        let (address_result, block_result) = join!(
+            load_pair_address_between(weth, token, dex.factory, provider.clone()),
+            provider.get_block_number()
+        );
+
+        let address = address_result?;
+        let synced_block = block_result
+            .map_err(|e| eyre::eyre!("Failed to get the current block number: {}", e))?;
+
+        let mut pair = UniswapV2Pair {
+            address,
+            token,
+            weth,
+            reserve_weth: U256::zero(),
+            reserve_token: U256::zero(),
+            transfer_tax: TransferTax::default(),
+            fee_bps: dex.fee_bps,
+            synced_block,
+        };
+
+        if address.is_zero() {
+            return Ok(pair);
+        }
+
+        let pair_abi = ethabi::Contract::load(UNISWAP_V2_PAIR.as_bytes())
+            .map_err(|e| eyre::eyre!("Failed to load v2 pair contract ABI: {}", e))?;
+        let pair_contract = Contract::new(address, pair_abi, provider);
+
+        let (reserve0, reserve1, _) = pair_contract
+            .method::<_, (u128, u128, u32)>("getReserves", ())
+            .map_err(|_| eyre::eyre!("Uniswap V2 Pair contract method not found"))?
+            .block(synced_block)
+            .call()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get pair reserves: {}", e))?;
+
+        pair.set_reserves(U256::from(reserve0), U256::from(reserve1));
+        Ok(pair)
+    }
+
+    pub fn with_transfer_tax(mut self, transfer_tax: TransferTax) -> Self {
+        self.transfer_tax = transfer_tax;
+        self
+    }
+
+    /// Adds liquidity that is not on-chain yet, e.g. a pending
+    /// `addLiquidityETH` we are backrunning.
+    pub fn with_added_liquidity(mut self, weth: U256, token: U256) -> Self {
+        self.reserve_weth += weth;
+        self.reserve_token += token;
+        self
+    }
+
+    fn set_reserves(&mut self, reserve0: U256, reserve1: U256) {
+        // token0 is always the lower address
+        if self.weth < self.token {
+            self.reserve_weth = reserve0;
+            self.reserve_token = reserve1;
+        } else {
+            self.reserve_weth = reserve1;
+            self.reserve_token = reserve0;
+        }
+    }
+
+    /// Applies a `Sync(uint112,uint112)` log emitted by this pair, e.g. to
+    /// replay the pair's history.
+    pub fn apply_sync(&mut self, log: &Log) {
+        if log.address != self.address || log.data.len() < 64 {
+            return;
+        }
+
+        self.set_reserves(
+            U256::from_big_endian(&log.data[0..32]),
+            U256::from_big_endian(&log.data[32..64]),
+        );
+
+        if let Some(block_number) = log.block_number {
+            self.synced_block = block_number;
+        }
+    }
+
+    /// Tokens that reach the buyer for `eth_in` wei, after the buy tax.
+    pub fn quote_buy(&self, eth_in: U256) -> U256 {
+        let amount_out =
+            get_amount_out(eth_in, self.reserve_weth, self.reserve_token, self.fee_bps);
+        self.transfer_tax.bought(amount_out)
+    }
+
+    /// Wei needed for `tokens_out` to reach the buyer, after the buy tax.
+    pub fn quote_buy_exact_out(&self, tokens_out: U256) -> Option<U256> {
+        let untaxed_bps = U256::from(BPS - self.transfer_tax.buy_bps.min(BPS - 1));
+        let amount_out = (tokens_out * U256::from(BPS) + untaxed_bps - 1) / untaxed_bps;
+        get_amount_in(
+            amount_out,
+            self.reserve_weth,
+            self.reserve_token,
+            self.fee_bps,
+        )
+    }
+
+    /// Wei received for selling `tokens_in`, after the sell tax.
+    pub fn quote_sell(&self, tokens_in: U256) -> U256 {
+        let reaches_pair = self.transfer_tax.sold(tokens_in);
+        get_amount_out(
+            reaches_pair,
+            self.reserve_token,
+            self.reserve_weth,
+            self.fee_bps,
+        )
+    }
+
+    /// Quotes a buy of `eth_in` wei and moves the reserves as the swap would,
+    /// so the next quote sees the price this buy leaves behind.
+    pub fn buy(&mut self, eth_in: U256) -> U256 {
+        let amount_out =
+            get_amount_out(eth_in, self.reserve_weth, self.reserve_token, self.fee_bps);
+        self.reserve_weth += eth_in;
+        self.reserve_token -= amount_out;
+        self.transfer_tax.bought(amount_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ether(amount: u64) -> U256 {
+        U256::from(amount) * U256::exp10(18)
+    }
+
+    /// 100 WETH against 1,000,000 tokens, WETH being token0.
+    fn pair(fee_bps: u64) -> UniswapV2Pair {
+        UniswapV2Pair {
+            address: H160::from_low_u64_be(3),
+            token: H160::from_low_u64_be(2),
+            weth: H160::from_low_u64_be(1),
+            reserve_weth: ether(100),
+            reserve_token: ether(1_000_000),
+            transfer_tax: TransferTax::default(),
+            fee_bps,
+            synced_block: U64::zero(),
+        }
+    }
+
+    #[test]
+    fn amount_out_matches_the_router() {
+        // in * (10000 - fee) * reserveOut / (reserveIn * 10000 + in * (10000 - fee))
+        assert_eq!(
+            get_amount_out(ether(1), ether(100), ether(1_000_000), 30),
+            U256::from_dec_str("9871580343970612988504").unwrap()
+        );
+        assert_eq!(
+            get_amount_out(ether(1), ether(100), ether(1_000_000), 25),
+            U256::from_dec_str("9876482091140869823510").unwrap()
+        );
+        assert_eq!(
+            get_amount_out(ether(1_000), ether(1_000_000), ether(100), 30),
+            U256::from_dec_str("99600698103990321").unwrap()
+        );
+    }
+
+    #[test]
+    fn amount_out_is_zero_without_input_or_liquidity() {
+        assert!(get_amount_out(U256::zero(), ether(100), ether(100), 30).is_zero());
+        assert!(get_amount_out(ether(1), U256::zero(), ether(100), 30).is_zero());
+        assert!(get_amount_out(ether(1), ether(100), U256::zero(), 30).is_zero());
+    }
+
+    #[test]
+    fn amount_in_rounds_up_like_the_router() {
+        // reserveIn * out * 10000 / ((reserveOut - out) * (10000 - fee)) + 1
+        let amount_in = get_amount_in(ether(1_000), ether(100), ether(1_000_000), 30).unwrap();
+        assert_eq!(amount_in, U256::from_dec_str("100401304012136510").unwrap());
+
+        assert!(get_amount_out(amount_in, ether(100), ether(1_000_000), 30) >= ether(1_000));
+        assert!(get_amount_out(amount_in - 1, ether(100), ether(1_000_000), 30) < ether(1_000));
+    }
+
+    #[test]
+    fn amount_in_is_none_when_the_pair_cannot_pay_out() {
+        assert_eq!(get_amount_in(ether(100), ether(10), ether(100), 30), None);
+        assert_eq!(get_amount_in(ether(101), ether(10), ether(100), 30), None);
+        assert_eq!(get_amount_in(ether(1), U256::zero(), ether(100), 30), None);
+        assert_eq!(get_amount_in(U256::zero(), ether(10), ether(100), 30), None);
+        assert_eq!(get_amount_in(ether(1), ether(10), ether(100), 10_000), None);
+    }
+
+    #[test]
+    fn transfer_tax_takes_its_share() {
+        let tax = TransferTax {
+            buy_bps: 500,
+            sell_bps: 1_000,
+        };
+        assert_eq!(tax.bought(U256::from(10_000)), U256::from(9_500));
+        assert_eq!(tax.sold(U256::from(10_000)), U256::from(9_000));
+
+        let no_tax = TransferTax::default();
+        assert_eq!(no_tax.bought(U256::from(10_000)), U256::from(10_000));
+
+        let full_tax = TransferTax {
+            buy_bps: 20_000,
+            sell_bps: 10_000,
+        };
+        assert!(full_tax.bought(U256::from(10_000)).is_zero());
+        assert!(full_tax.sold(U256::from(10_000)).is_zero());
+    }
+
+    #[test]
+    fn quotes_apply_the_fee_and_tax() {
+        let taxed = pair(25).with_transfer_tax(TransferTax {
+            buy_bps: 500,
+            sell_bps: 1_000,
+        });
+
+        let untaxed_out = get_amount_out(ether(1), ether(100), ether(1_000_000), 25);
+        assert_eq!(taxed.quote_buy(ether(1)), untaxed_out * 9_500 / 10_000);
+
+        let reaches_pair = ether(1_000) * 9_000 / 10_000;
+        assert_eq!(
+            taxed.quote_sell(ether(1_000)),
+            get_amount_out(reaches_pair, ether(1_000_000), ether(100), 25)
+        );
+
+        let eth_in = taxed.quote_buy_exact_out(ether(1_000)).unwrap();
+        assert!(taxed.quote_buy(eth_in) >= ether(1_000));
+        assert_eq!(taxed.quote_buy_exact_out(ether(1_000_000)), None);
+    }
+
+    #[test]
+    fn buy_moves_the_reserves() {
+        let mut pair = pair(30);
+        let first_quote = pair.quote_buy(ether(1));
+
+        assert_eq!(pair.buy(ether(1)), first_quote);
+        assert_eq!(pair.reserve_weth, ether(101));
+        assert_eq!(pair.reserve_token, ether(1_000_000) - first_quote);
+
+        // The second buy pays the higher price the first one left behind
+        assert!(pair.buy(ether(1)) < first_quote);
+    }
+
+    #[test]
+    fn sync_sets_the_reserves_in_token_order() {
+        let mut pair = pair(30);
+        let mut data = [0u8; 64];
+        U256::from(7).to_big_endian(&mut data[0..32]);
+        U256::from(9).to_big_endian(&mut data[32..64]);
+
+        let log = Log {
+            address: pair.address,
+            data: data.to_vec().into(),
+            block_number: Some(U64::from(12)),
+            ..Default::default()
+        };
+        pair.apply_sync(&log);
+
+        // WETH has the lower address, so it is reserve0
+        assert_eq!(pair.reserve_weth, U256::from(7));
+        assert_eq!(pair.reserve_token, U256::from(9));
+        assert_eq!(pair.synced_block, U64::from(12));
+
+        let other_pair = Log {
+            address: H160::from_low_u64_be(4),
+            ..log
+        };
+        pair.apply_sync(&Log {
+            data: vec![0u8; 64].into(),
+            ..other_pair
+        });
+        assert_eq!(pair.reserve_weth, U256::from(7));
+    }
+}
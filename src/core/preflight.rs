@@ -0,0 +1,163 @@
+use ethers::prelude::*;
+use ethers::types::{BlockNumber, H160, U256};
+use ethers::utils::{format_ether, format_units};
+use futures::future::join_all;
+use std::sync::Arc;
+
+use crate::core::chain::GasModel;
+use crate::core::contracts::ERC20;
+use crate::core::gas::{estimate_fees, GasLimitPolicy};
+use crate::core::public_txn::APPROVE_GAS_LIMIT;
+
+/// Funds one wallet needs for a run versus what it holds.
+#[derive(Debug, Clone)]
+pub struct WalletPreflight {
+    pub name: String,
+    pub address: H160,
+    pub balance: U256,
+    pub pending_nonce: U256,
+    pub required: U256,
+}
+
+impl WalletPreflight {
+    pub fn is_funded(&self) -> bool {
+        self.balance >= self.required
+    }
+}
+
+/// Queries every wallet's balance and pending nonce in parallel and compares
+/// the balance against what `rounds` swaps of `value` cost at the current max
+/// fee, each at the highest gas limit `gas_policy` can set, its cap. With
+/// `sells` the swaps are sells, which add one router approval. Prints the
+/// result as a table.
+pub async fn check_wallets(
+    wallets: Vec<(String, H160, U256)>,
+    rounds: u32,
+    sells: bool,
+    buy_extra_gas: U256,
+    gas_policy: &GasLimitPolicy,
+    gas_model: GasModel,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<Vec<WalletPreflight>> {
+    let (max_fee_per_gas, _) = estimate_fees(provider.as_ref(), gas_model)
+        .await
+        .map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let max_fee_per_gas = max_fee_per_gas + buy_extra_gas;
+    let swap_gas_cost = max_fee_per_gas * U256::from(gas_policy.cap);
+    let approve_gas_cost = match sells {
+        true => max_fee_per_gas * U256::from(APPROVE_GAS_LIMIT),
+        false => U256::zero(),
+    };
+
+    let checks = join_all(wallets.into_iter().map(|(name, address, value)| {
+        let provider = provider.clone();
+        async move {
+            let (balance, pending_nonce) = futures::join!(
+                provider.get_balance(address, None),
+                provider.get_transaction_count(address, Some(BlockNumber::Pending.into())),
+            );
+
+            Ok::<_, eyre::Report>(WalletPreflight {
+                balance: balance
+                    .map_err(|e| eyre::eyre!("Failed to get balance of {}: {}", name, e))?,
+                pending_nonce: pending_nonce
+                    .map_err(|e| eyre::eyre!("Failed to get nonce of {}: {}", name, e))?,
+                required: (value + swap_gas_cost) * U256::from(rounds) + approve_gas_cost,
+                name,
+                address,
+            })
+        }
+    }))
+    .await;
+
+    let mut preflight = checks.into_iter().collect::<eyre::Result<Vec<_>>>()?;
+    preflight.sort_by(|a, b| a.name.cmp(&b.name));
+
+    print_table(&preflight);
+
+    Ok(preflight)
+}
+
+fn print_table(preflight: &[WalletPreflight]) {
+    println!(
+        "{:<12} {:<42} {:>7} {:>22} {:>22}  status",
+        "wallet", "address", "nonce", "required ETH", "available ETH"
+    );
+
+    for wallet in preflight {
+        println!(
+            "{:<12} {:<42} {:>7} {:>22} {:>22}  {}",
+            wallet.name,
+            format!("{:?}", wallet.address),
+            wallet.pending_nonce,
+            format_ether(wallet.required),
+            format_ether(wallet.balance),
+            if wallet.is_funded() {
+                "ok"
+            } else {
+                "UNDERFUNDED"
+            }
+        );
+    }
+}
+
+/// Prints every wallet's ETH and `token` balances as a table.
+pub async fn print_balances(
+    wallets: Vec<(String, H160)>,
+    token: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<()> {
+    let erc20_abi = ethabi::Contract::load(ERC20.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load ERC20 contract ABI: {}", e))?;
+    let erc20_contract = Contract::new(token, erc20_abi, provider.clone());
+
+    let decimals = erc20_contract
+        .method::<_, u8>("decimals", ())
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get token decimals: {}", e))?;
+
+    let balances = join_all(wallets.into_iter().map(|(name, address)| {
+        let provider = provider.clone();
+        let erc20_contract = &erc20_contract;
+        async move {
+            let balance_of = erc20_contract
+                .method::<_, U256>("balanceOf", address)
+                .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?;
+            let (eth_balance, token_balance) =
+                futures::join!(provider.get_balance(address, None), balance_of.call());
+
+            Ok::<_, eyre::Report>((
+                name.clone(),
+                address,
+                eth_balance.map_err(|e| eyre::eyre!("Failed to get balance of {}: {}", name, e))?,
+                token_balance
+                    .map_err(|e| eyre::eyre!("Failed to get token balance of {}: {}", name, e))?,
+            ))
+        }
+    }))
+    .await;
+
+    let mut balances = balances.into_iter().collect::<eyre::Result<Vec<_>>>()?;
+    balances.sort_by(|a, b| a.0.cmp(&b.0));
+
+    println!(
+        "{:<12} {:<42} {:>22} {:>28}",
+        "wallet", "address", "ETH", "tokens"
+    );
+
+    for (name, address, eth_balance, token_balance) in balances {
+        println!(
+            "{:<12} {:<42} {:>22} {:>28}",
+            name,
+            format!("{:?}", address),
+            format_ether(eth_balance),
+            format_units(token_balance, decimals as u32)
+                .map_err(|e| eyre::eyre!("Failed to format token balance: {}", e))?
+        );
+    }
+
+    Ok(())
+}
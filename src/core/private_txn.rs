@@ -1,41 +1,86 @@
 use ethers::types::transaction::eip2718::TypedTransaction;
-use ethers_flashbots::BundleRequest;
+use ethers_flashbots::{BundleRequest, PendingBundleError};
 use spinners::{Spinner, Spinners};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 
 use crate::core::contracts::{
-    deadline_timestamp, load_flashbots_client_middleware, load_uniswap_v2_mempool, WETH_ADDRESS,
+    apply_slippage, deadline_timestamp, load_erc20_contract, load_uniswap_v2_mempool,
+    resolve_sell_amount, BundleClient, SellAmount,
 };
+use crate::core::dry_run::{report_simulated_bundle, with_balance_probes, DryRunStep};
+use crate::core::gas::{estimate_fees, GasLimitPolicy, SendSettings};
+use crate::core::pair::UniswapV2Pair;
+use crate::core::public_txn::APPROVE_GAS_LIMIT;
+use crate::core::report::SubmissionReport;
+use crate::core::router::{quote_buy, quote_sell, V2Trade};
+use crate::core::v3::{v3_buy_request, v3_sell_request, V3Trade};
 
 use ethers::prelude::*;
 use ethers::types::U256;
 use ethers_core::types::Eip1559TransactionRequest;
 use ethers_signers::Signer;
+use futures::future::join_all;
+use log::debug;
 use tokio::join;
 
+/// A transaction inside a bundle, kept unsigned where possible so it can be
+/// re-signed with fresh fees when the bundle is re-submitted.
+#[derive(Debug, Clone)]
+pub enum BundleTransactionSource {
+    /// Someone else's signed transaction, included as-is.
+    Foreign(Bytes),
+    /// One of our own transactions, signed by `wallet` on every submission.
+    Own {
+        wallet: LocalWallet,
+        request: Box<Eip1559TransactionRequest>,
+    },
+}
+
+/// The relay a bundle goes to and how many target blocks it may take to land.
+#[derive(Debug, Clone, Copy)]
+pub struct BundleTarget<'a> {
+    pub client: &'a BundleClient,
+    pub block_budget: u64,
+}
+
 pub async fn uniswap_v2_bundler(
     value: U256,
-    token_address: &str,
-    slippage_percentage: f64,
-    buy_extra_gas: U256,
-    miner_tip: U256,
     maxbuy_amount: U256,
+    trade: &V2Trade<'_>,
     wallet: &LocalWallet,
     provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    target: BundleTarget<'_>,
 ) -> eyre::Result<()> {
+    let V2Trade {
+        token_address,
+        slippage_percentage,
+        dex,
+        ..
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
+
     let mut sp = Spinner::new(Spinners::Dots9, "Waiting for transaction hash...".into());
-    let (nonce_result, gas_details_result, uniswap_v2_contract_result, client_result) = join!(
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (nonce_result, gas_details_result, uniswap_v2_contract_result) = join!(
         provider.get_transaction_count(wallet.address(), None),
-        provider.estimate_eip1559_fees(None),
-        load_uniswap_v2_mempool(wallet, provider.clone()),
-        load_flashbots_client_middleware(&wallet, wallet, provider.clone().into(),)
+        estimate_fees(provider.as_ref(), gas_model),
+        load_uniswap_v2_mempool(dex.router, wallet, provider.clone()),
     );
 
     let nonce = nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
 
     let (max_fee_per_gas, max_priority_fee_per_gas) =
-        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate EIP-1559 fees"))?;
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
 
     let uniswap_v2_contract = uniswap_v2_contract_result.or_else(|e| {
         Err(eyre::eyre!(
@@ -44,169 +89,896 @@ pub async fn uniswap_v2_bundler(
         ))
     })?;
 
-    let client =
-        client_result.or_else(|e| Err(eyre::eyre!("Failed to load client middleware: {}", e)))?;
-
-    // Run the asynchronous operations in parallel
+    let quote = quote_buy(
+        value,
+        maxbuy_amount,
+        trade,
+        &uniswap_v2_contract,
+        provider.clone(),
+    )
+    .await?;
+    let path = quote.path;
+    let value_to_use = quote.amount_in;
+    let last_token_value = quote.amount_out;
+
+    debug!("value: {:?}, route: {:?}", value_to_use, path);
+
+    if last_token_value.is_zero() {
+        sp.stop();
+        return Err(eyre::eyre!("No liquidity to buy {} against", token_address));
+    }
 
-    let tokenaddress = H160::from_str(token_address)?;
-    let path = vec![*WETH_ADDRESS, tokenaddress];
+    println!("Slippage percentage: {}", slippage_percentage);
 
-    let mut value_to_use = value; // Define a new variable to store the resultant value
+    let amount_out_tokens = apply_slippage(last_token_value, slippage_percentage)?;
 
-    if maxbuy_amount != U256::zero() {
-        let get_input_ether_method = uniswap_v2_contract
-            .method::<_, Vec<U256>>("getAmountsIn", (maxbuy_amount, path.clone()))
-            .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
+    let to = wallet.address();
 
-        let get_input_ether_result = get_input_ether_method.call().await;
+    let call_data = uniswap_v2_contract
+        .method::<_, H160>(
+            "swapExactETHForTokensSupportingFeeOnTransferTokens",
+            (amount_out_tokens, path.clone(), to, deadline_timestamp()),
+        )
+        .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
 
-        let get_input_ether = get_input_ether_result
-            .map_err(|e| eyre::eyre!("Failed to get amount of Intokens: {}", e))?;
+    let transaction_data = call_data
+        .calldata()
+        .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
 
-        println!("get_input_ether: {:?}", get_input_ether);
+    println!("Transaction data: {:?}", transaction_data);
+    let txn_request = Eip1559TransactionRequest::new()
+        .from(wallet.address())
+        .to(uniswap_v2_contract.address())
+        .value(value_to_use)
+        .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+        .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+        .data(transaction_data)
+        .nonce(nonce);
 
-        value_to_use = if value > get_input_ether[0] {
-            get_input_ether[0]
-        } else {
-            value
-        };
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    let bundle_swap_ethfor_tokens_v2 = vec![BundleTransactionSource::Own {
+        wallet: wallet.clone(),
+        request: Box::new(txn_request),
+    }];
+
+    if dry_run {
+        let steps = [DryRunStep::Buy {
+            token: tokenaddress,
+            amount_in: value_to_use,
+            expected_out: Some(last_token_value),
+        }];
+        return dry_run_bundle(
+            target.client,
+            provider,
+            &bundle_swap_ethfor_tokens_v2,
+            &steps,
+            send,
+            &mut sp,
+        )
+        .await;
     }
 
-    println!("value: {:?}", value);
+    submit_bundle_until_included(
+        target,
+        &provider,
+        Some(gas_policy),
+        bundle_swap_ethfor_tokens_v2,
+        send,
+        &mut sp,
+    )
+    .await
+}
 
-    let get_output_tokens_method = uniswap_v2_contract
-        .method::<_, Vec<U256>>("getAmountsOut", (value_to_use, path.clone()))
-        .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
+pub async fn uniswap_v2_sell_bundler(
+    sell_amount: SellAmount,
+    trade: &V2Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    target: BundleTarget<'_>,
+) -> eyre::Result<()> {
+    let V2Trade {
+        token_address,
+        slippage_percentage,
+        dex,
+        ..
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
 
-    let get_output_tokens_result = get_output_tokens_method.call().await;
+    let mut sp = Spinner::new(Spinners::Dots9, "Waiting for transaction hash...".into());
+    let tokenaddress = H160::from_str(token_address)?;
 
-    let get_output_tokens = get_output_tokens_result
-        .map_err(|e| eyre::eyre!("Failed to get amount of Outtokens: {}", e))?;
+    let (nonce_result, gas_details_result, uniswap_v2_contract_result, erc20_contract_result) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+        load_uniswap_v2_mempool(dex.router, wallet, provider.clone()),
+        load_erc20_contract(tokenaddress, wallet, provider.clone()),
+    );
 
-    // Ensure get_output_tokens is not empty
-    let last_token_value = match get_output_tokens.last() {
-        Some(value) => *value,
-        None => return Err(eyre::eyre!("get_output_tokens is empty")),
-    };
+    let mut nonce =
+        nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
 
-    println!("Slippage percentage: {}", slippage_percentage);
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
 
-    // Calculate the slippage adjustment
-    let slippage_multiplier = U256::from((slippage_percentage * 1e18) as u128);
-    let slippage_adjustment = last_token_value
-        .checked_mul(slippage_multiplier)
-        .and_then(|result| result.checked_div(U256::from(1_000_000_000_000_000_000u128)))
-        .ok_or_else(|| eyre::eyre!("Overflow occurred during slippage adjustment calculation"))?;
+    let uniswap_v2_contract = uniswap_v2_contract_result
+        .map_err(|e| eyre::eyre!("Failed to load uniswap v2 mempool contract: {}", e))?;
 
-    let amount_out_tokens = last_token_value
-        .checked_sub(slippage_adjustment)
-        .ok_or_else(|| eyre::eyre!("Overflow occurred during amount out tokens calculation"))?;
+    let erc20_contract =
+        erc20_contract_result.map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
 
-    let to = wallet.address();
+    let amount_in = resolve_sell_amount(&erc20_contract, wallet.address(), sell_amount).await?;
+
+    if amount_in.is_zero() {
+        sp.stop();
+        return Err(eyre::eyre!("No {} balance to sell", token_address));
+    }
+
+    let mut bundle_swap_tokensfor_eth_v2 = Vec::new();
+    let mut steps = Vec::new();
+
+    let allowance = erc20_contract
+        .method::<_, U256>(
+            "allowance",
+            (wallet.address(), uniswap_v2_contract.address()),
+        )
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get token allowance: {}", e))?;
+
+    // The approval rides in the same bundle, right before the swap
+    if allowance < amount_in {
+        let approve_data = erc20_contract
+            .method::<_, bool>("approve", (uniswap_v2_contract.address(), U256::MAX))
+            .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+            .calldata()
+            .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+        let approve_request = Eip1559TransactionRequest::new()
+            .from(wallet.address())
+            .to(erc20_contract.address())
+            .gas(U256::from(APPROVE_GAS_LIMIT))
+            .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+            .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+            .data(approve_data)
+            .nonce(nonce);
+
+        bundle_swap_tokensfor_eth_v2.push(BundleTransactionSource::Own {
+            wallet: wallet.clone(),
+            request: Box::new(approve_request),
+        });
+        steps.push(DryRunStep::Other("approve"));
+
+        nonce += U256::one();
+    }
+
+    let quote = quote_sell(amount_in, trade, &uniswap_v2_contract, provider.clone()).await?;
+    let path = quote.path;
+    let last_ether_value = quote.amount_out;
+    if last_ether_value.is_zero() {
+        sp.stop();
+        return Err(eyre::eyre!(
+            "No liquidity to sell {} against",
+            token_address
+        ));
+    }
+
+    let amount_out_ether = apply_slippage(last_ether_value, slippage_percentage)?;
 
     let call_data = uniswap_v2_contract
-        .method::<_, H160>(
-            "swapExactETHForTokensSupportingFeeOnTransferTokens",
-            (amount_out_tokens, path.clone(), to, deadline_timestamp()),
+        .method::<_, ()>(
+            "swapExactTokensForETHSupportingFeeOnTransferTokens",
+            (
+                amount_in,
+                amount_out_ether,
+                path.clone(),
+                wallet.address(),
+                deadline_timestamp(),
+            ),
         )
-        .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
+        .map_err(|_| eyre::eyre!("Uniswap V2 Router contract method not found"))?;
 
     let transaction_data = call_data
         .calldata()
         .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
-
-    println!("Transaction data: {:?}", transaction_data);
-
-    let estimated_gas = U256::from(313252);
-    // // // Constructing the EIP1559 transaction
     let txn_request = Eip1559TransactionRequest::new()
         .from(wallet.address())
         .to(uniswap_v2_contract.address())
-        .value(value_to_use)
-        .gas(estimated_gas)
         .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
         .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
         .data(transaction_data)
         .nonce(nonce);
 
-    let block_number = client
-        .0
-        .get_block_number()
-        .await
-        .map_err(|e| eyre::eyre!("Failed to get the current block number: {}", e))?;
-    let mut tx = TypedTransaction::Eip1559(txn_request.clone());
-    client
-        .0
-        .fill_transaction(&mut tx, None)
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    bundle_swap_tokensfor_eth_v2.push(BundleTransactionSource::Own {
+        wallet: wallet.clone(),
+        request: Box::new(txn_request),
+    });
+    steps.push(DryRunStep::Sell {
+        token: tokenaddress,
+        amount_in,
+        expected_out: Some(last_ether_value),
+    });
+
+    if dry_run {
+        return dry_run_bundle(
+            target.client,
+            provider,
+            &bundle_swap_tokensfor_eth_v2,
+            &steps,
+            send,
+            &mut sp,
+        )
+        .await;
+    }
+
+    submit_bundle_until_included(
+        target,
+        &provider,
+        Some(gas_policy),
+        bundle_swap_tokensfor_eth_v2,
+        send,
+        &mut sp,
+    )
+    .await
+}
+
+pub async fn uniswap_v3_bundler(
+    value: U256,
+    trade: &V3Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    target: BundleTarget<'_>,
+) -> eyre::Result<()> {
+    let V3Trade {
+        token_address,
+        slippage_percentage,
+        route_config,
+    } = *trade;
+    let SendSettings {
+        gas_policy,
+        dry_run,
+        ..
+    } = *send;
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Waiting for transaction hash...".into());
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (nonce_result, request_result) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        v3_buy_request(
+            value,
+            tokenaddress,
+            slippage_percentage,
+            route_config,
+            wallet.address(),
+            provider.clone(),
+        ),
+    );
+
+    let nonce = nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+    // Fees are filled in by `submit_bundle_until_included` on every attempt
+    let txn_request = request_result?.nonce(nonce);
+
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    let bundle_swap_ethfor_tokens_v3 = vec![BundleTransactionSource::Own {
+        wallet: wallet.clone(),
+        request: Box::new(txn_request),
+    }];
+
+    if dry_run {
+        let steps = [DryRunStep::Buy {
+            token: tokenaddress,
+            amount_in: value,
+            expected_out: None,
+        }];
+        return dry_run_bundle(
+            target.client,
+            provider,
+            &bundle_swap_ethfor_tokens_v3,
+            &steps,
+            send,
+            &mut sp,
+        )
+        .await;
+    }
+
+    submit_bundle_until_included(
+        target,
+        &provider,
+        Some(gas_policy),
+        bundle_swap_ethfor_tokens_v3,
+        send,
+        &mut sp,
+    )
+    .await
+}
+
+pub async fn uniswap_v3_sell_bundler(
+    sell_amount: SellAmount,
+    trade: &V3Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    target: BundleTarget<'_>,
+) -> eyre::Result<()> {
+    let V3Trade {
+        token_address,
+        slippage_percentage,
+        route_config,
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Waiting for transaction hash...".into());
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (nonce_result, gas_details_result, erc20_contract_result) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+        load_erc20_contract(tokenaddress, wallet, provider.clone()),
+    );
+
+    let mut nonce =
+        nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let erc20_contract =
+        erc20_contract_result.map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
+
+    let amount_in = resolve_sell_amount(&erc20_contract, wallet.address(), sell_amount).await?;
+
+    if amount_in.is_zero() {
+        sp.stop();
+        return Err(eyre::eyre!("No {} balance to sell", token_address));
+    }
+
+    let mut bundle_swap_tokensfor_eth_v3 = Vec::new();
+    let mut steps = Vec::new();
+
+    let allowance = erc20_contract
+        .method::<_, U256>(
+            "allowance",
+            (wallet.address(), route_config.deployment.swap_router_02),
+        )
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
         .await
-        .map_err(|e| eyre::eyre!("Failed to fill the transaction: {}", e))?;
+        .map_err(|e| eyre::eyre!("Failed to get token allowance: {}", e))?;
+
+    // The approval rides in the same bundle, right before the swap
+    if allowance < amount_in {
+        let approve_data = erc20_contract
+            .method::<_, bool>(
+                "approve",
+                (route_config.deployment.swap_router_02, U256::MAX),
+            )
+            .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+            .calldata()
+            .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+        let approve_request = Eip1559TransactionRequest::new()
+            .from(wallet.address())
+            .to(erc20_contract.address())
+            .gas(U256::from(APPROVE_GAS_LIMIT))
+            .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+            .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+            .data(approve_data)
+            .nonce(nonce);
+
+        bundle_swap_tokensfor_eth_v3.push(BundleTransactionSource::Own {
+            wallet: wallet.clone(),
+            request: Box::new(approve_request),
+        });
+        steps.push(DryRunStep::Other("approve"));
+
+        nonce += U256::one();
+    }
+
+    let txn_request = v3_sell_request(
+        amount_in,
+        tokenaddress,
+        slippage_percentage,
+        route_config,
+        wallet.address(),
+        provider.clone(),
+    )
+    .await?
+    .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+    .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+    .nonce(nonce);
+
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    bundle_swap_tokensfor_eth_v3.push(BundleTransactionSource::Own {
+        wallet: wallet.clone(),
+        request: Box::new(txn_request),
+    });
+    steps.push(DryRunStep::Sell {
+        token: tokenaddress,
+        amount_in,
+        expected_out: None,
+    });
+
+    if dry_run {
+        return dry_run_bundle(
+            target.client,
+            provider,
+            &bundle_swap_tokensfor_eth_v3,
+            &steps,
+            send,
+            &mut sp,
+        )
+        .await;
+    }
+
+    submit_bundle_until_included(
+        target,
+        &provider,
+        Some(gas_policy),
+        bundle_swap_tokensfor_eth_v3,
+        send,
+        &mut sp,
+    )
+    .await
+}
+
+/// Builds one bundle that starts with `foreign_txs` (e.g. an observed
+/// `addLiquidityETH` from the mempool) in the given order, followed by one
+/// buy per wallet in `buys`. With no foreign transactions this is the
+/// single-bundle mode: every wallet's buy for a round lands in the same
+/// block. The bundle is simulated as a whole and only sent if every
+/// transaction in it succeeds, so all wallets buy atomically right behind
+/// the foreign transactions.
+///
+/// `added_liquidity` is the `(WETH, token)` amount the foreign transactions
+/// add to the pair; each buy's `amountOutMin` is computed against the
+/// reserves left after the buys before it. Every buy in `buys` carries its
+/// own slippage, which is used instead of `trade.slippage_percentage`.
+pub async fn uniswap_v2_multi_wallet_bundler(
+    foreign_txs: Vec<Bytes>,
+    added_liquidity: Option<(U256, U256)>,
+    buys: Vec<(&LocalWallet, U256, f64)>,
+    trade: &V2Trade<'_>,
+    provider: Arc<Provider<Http>>,
+    send: &SendSettings<'_>,
+    target: BundleTarget<'_>,
+) -> eyre::Result<()> {
+    let V2Trade {
+        token_address,
+        transfer_tax,
+        dex,
+        weth,
+        ..
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
+
+    let first_wallet = match buys.first() {
+        Some((wallet, _, _)) => *wallet,
+        None => return Err(eyre::eyre!("Bundle has no wallets to buy with")),
+    };
 
-    let signature = client
-        .0
-        .signer()
+    let mut sp = Spinner::new(Spinners::Dots9, "Waiting for transaction hash...".into());
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (gas_details_result, uniswap_v2_contract_result, pair_result) = join!(
+        estimate_fees(provider.as_ref(), gas_model),
+        load_uniswap_v2_mempool(dex.router, first_wallet, provider.clone()),
+        UniswapV2Pair::load(tokenaddress, dex, weth, provider.clone()),
+    );
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let uniswap_v2_contract = uniswap_v2_contract_result
+        .map_err(|e| eyre::eyre!("Failed to load uniswap v2 mempool contract: {}", e))?;
+
+    let mut pair = pair_result?.with_transfer_tax(transfer_tax);
+    if let Some((added_eth, added_token)) = added_liquidity {
+        pair = pair.with_added_liquidity(added_eth, added_token);
+    }
+
+    let mut steps = vec![DryRunStep::Other("foreign transaction"); foreign_txs.len()];
+    let mut bundle: Vec<BundleTransactionSource> = foreign_txs
+        .into_iter()
+        .map(BundleTransactionSource::Foreign)
+        .collect();
+
+    let path = vec![weth, tokenaddress];
+
+    // Every wallet's pending nonce at once, so transactions still in the
+    // mempool are not signed over
+    let nonces = join_all(buys.iter().map(|(wallet, _, _)| {
+        provider.get_transaction_count(wallet.address(), Some(BlockNumber::Pending.into()))
+    }))
+    .await;
+
+    for ((wallet, value, slippage_percentage), nonce_result) in buys.into_iter().zip(nonces) {
+        let nonce =
+            nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+        // The next wallet buys against the reserves this swap leaves behind
+        let expected_tokens = pair.buy(value);
+        if expected_tokens.is_zero() {
+            return Err(eyre::eyre!("No liquidity to buy {} against", token_address));
+        }
+
+        let amount_out_tokens = apply_slippage(expected_tokens, slippage_percentage)?;
+
+        let call_data = uniswap_v2_contract
+            .method::<_, H160>(
+                "swapExactETHForTokensSupportingFeeOnTransferTokens",
+                (
+                    amount_out_tokens,
+                    path.clone(),
+                    wallet.address(),
+                    deadline_timestamp(),
+                ),
+            )
+            .map_err(|_| eyre::eyre!("Uniswap V2 Router contract method not found"))?;
+
+        let transaction_data = call_data
+            .calldata()
+            .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+        let txn_request = Eip1559TransactionRequest::new()
+            .from(wallet.address())
+            .to(uniswap_v2_contract.address())
+            .value(value)
+            .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+            .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+            .data(transaction_data)
+            .nonce(nonce);
+
+        let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+        let txn_request = txn_request.gas(estimated_gas);
+
+        bundle.push(BundleTransactionSource::Own {
+            wallet: wallet.clone(),
+            request: Box::new(txn_request),
+        });
+        steps.push(DryRunStep::Buy {
+            token: tokenaddress,
+            amount_in: value,
+            expected_out: Some(expected_tokens),
+        });
+    }
+
+    if dry_run {
+        return dry_run_bundle(target.client, provider, &bundle, &steps, send, &mut sp).await;
+    }
+
+    submit_bundle_until_included(target, &provider, Some(gas_policy), bundle, send, &mut sp).await
+}
+
+/// Signs `txn_request` directly with `wallet`. Gas, fees and nonce must
+/// already be set.
+pub async fn sign_wallet_transaction(
+    wallet: &LocalWallet,
+    txn_request: Eip1559TransactionRequest,
+) -> eyre::Result<Bytes> {
+    let tx = TypedTransaction::Eip1559(
+        txn_request
+            .from(wallet.address())
+            .chain_id(wallet.chain_id()),
+    );
+
+    let signature = wallet
         .sign_transaction(&tx)
         .await
         .map_err(|e| eyre::eyre!("Failed to sign the transaction: {}", e))?;
 
-    let rlp_signed_tx = tx.rlp_signed(&signature);
+    Ok(tx.rlp_signed(&signature))
+}
 
-    let bundle_swap_ethfor_tokens_v2 = BundleRequest::new()
-        .push_transaction(rlp_signed_tx.clone())
-        .set_block(block_number)
-        .set_simulation_block(block_number)
+/// Signs every transaction in `transactions` with the given fees and packs
+/// them into a bundle targeting `target_block`.
+async fn sign_bundle(
+    transactions: &[BundleTransactionSource],
+    target_block: U64,
+    simulation_block: U64,
+    max_fee_per_gas: U256,
+    max_priority_fee_per_gas: U256,
+) -> eyre::Result<BundleRequest> {
+    let mut bundle = BundleRequest::new()
+        .set_block(target_block)
+        .set_simulation_block(simulation_block)
         .set_simulation_timestamp(0);
 
+    for transaction in transactions.iter() {
+        let rlp_signed_tx = match transaction {
+            BundleTransactionSource::Foreign(raw_tx) => raw_tx.clone(),
+            BundleTransactionSource::Own { wallet, request } => {
+                let request = request
+                    .as_ref()
+                    .clone()
+                    .max_priority_fee_per_gas(max_priority_fee_per_gas)
+                    .max_fee_per_gas(max_fee_per_gas);
+
+                sign_wallet_transaction(wallet, request).await?
+            }
+        };
+
+        bundle = bundle.push_transaction(rlp_signed_tx);
+    }
+
+    Ok(bundle)
+}
+
+/// Signs `transactions` with current fees exactly as the first submission
+/// would and runs them through `simulate_bundle` only. Nothing is sent to a
+/// builder. Every buy is bracketed with `balanceOf` probes so the report shows
+/// the tokens actually received.
+pub async fn dry_run_bundle(
+    client: &BundleClient,
+    provider: Arc<Provider<Http>>,
+    transactions: &[BundleTransactionSource],
+    steps: &[DryRunStep],
+    send: &SendSettings<'_>,
+    sp: &mut Spinner,
+) -> eyre::Result<()> {
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_model,
+        ..
+    } = *send;
+
+    let (block_number_result, gas_details_result) = join!(
+        provider.get_block_number(),
+        estimate_fees(provider.as_ref(), gas_model)
+    );
+
+    let block_number = block_number_result
+        .map_err(|e| eyre::eyre!("Failed to get the current block number: {}", e))?;
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let (transactions, layout) = with_balance_probes(transactions, steps)?;
+
+    let bundle = sign_bundle(
+        &transactions,
+        block_number + 1,
+        block_number,
+        max_fee_per_gas + buy_extra_gas,
+        max_priority_fee_per_gas + miner_tip,
+    )
+    .await?;
+
     let simulated_bundle = client
         .1
-        .inner()
-        .simulate_bundle(&bundle_swap_ethfor_tokens_v2)
+        .simulate_bundle(&bundle)
         .await
         .map_err(|e| eyre::eyre!("Failed to simulate the bundle: {}", e))?;
-    println!(
-        "Simulated bundle for block {}: {:?}",
-        block_number, simulated_bundle
-    );
 
-    let pending_bundle_swap_ethfor_tokens_v2 = client
-        .0
-        .inner()
-        .send_bundle(&bundle_swap_ethfor_tokens_v2)
-        .await
-        .map_err(|e| eyre::eyre!("Failed to send the bundle: {}", e))?;
-
-    //   let mut pending_tx_hash = H256::zero();
-
-    for response in pending_bundle_swap_ethfor_tokens_v2.iter() {
-        match response {
-            Ok(pending_bundle) => {
-                let pending_bundle = pending_bundle;
-
-                // Transaction hash from the bundle
-                match pending_bundle.transactions.get(0) {
-                    Some(hash) => {
-                        let pending_tx_hash = *hash;
-                        sp.stop_with_message(format!(
-                            "Transaction hash found: {:?}",
-                            pending_tx_hash
-                        ));
-                        sp.stop();
-                        break;
-                    }
-                    None => {
-                        // Error Return
-                        sp.stop();
-                        return Err(eyre::eyre!("Failed to get the transaction hash"));
+    sp.stop_with_message(format!(
+        "Dry run: simulated bundle for block {}, not sent",
+        block_number + 1
+    ));
+
+    report_simulated_bundle(steps, &layout, &simulated_bundle.transactions, provider).await
+}
+
+/// Submits `transactions` as a bundle targeting the block after the current
+/// one and waits for that block. If the bundle did not land, our own
+/// transactions are re-signed with refreshed fees and the bundle is
+/// re-submitted for the next block, until it lands or the target's
+/// `block_budget` blocks have passed.
+pub async fn submit_bundle_until_included(
+    target: BundleTarget<'_>,
+    provider: &Provider<Http>,
+    gas_policy: Option<&GasLimitPolicy>,
+    mut transactions: Vec<BundleTransactionSource>,
+    send: &SendSettings<'_>,
+    sp: &mut Spinner,
+) -> eyre::Result<()> {
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_model,
+        ..
+    } = *send;
+    let BundleTarget {
+        client,
+        block_budget,
+    } = target;
+    let mut gas_tightened = gas_policy.is_none();
+
+    for attempt in 1..=block_budget {
+        let (block_number_result, gas_details_result) = join!(
+            provider.get_block_number(),
+            estimate_fees(provider, gas_model)
+        );
+
+        let block_number = block_number_result
+            .map_err(|e| eyre::eyre!("Failed to get the current block number: {}", e))?;
+
+        let (max_fee_per_gas, max_priority_fee_per_gas) =
+            gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+        // The current block is already mined, so target the next one
+        let target_block = block_number + 1;
+
+        let max_priority_fee_per_gas = max_priority_fee_per_gas + miner_tip;
+        let max_fee_per_gas = max_fee_per_gas + buy_extra_gas;
+
+        let mut bundle = sign_bundle(
+            &transactions,
+            target_block,
+            block_number,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        )
+        .await?;
+
+        let simulated_bundle = client
+            .1
+            .simulate_bundle(&bundle)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to simulate the bundle: {}", e))?;
+        println!(
+            "Simulated bundle for block {}: {:?}",
+            target_block, simulated_bundle
+        );
+
+        // Never send a bundle in which any transaction fails
+        for simulated_tx in simulated_bundle.transactions.iter() {
+            if let Some(error) = simulated_tx.error.as_ref().or(simulated_tx.revert.as_ref()) {
+                sp.stop();
+                return Err(eyre::eyre!(
+                    "Bundle simulation failed for {:?}: {}",
+                    simulated_tx.hash,
+                    error
+                ));
+            }
+        }
+
+        // The first successful simulation tells us what each of our
+        // transactions really uses, so tighten their gas limits once and
+        // re-sign before anything is sent.
+        if let (false, Some(policy)) = (gas_tightened, gas_policy) {
+            for (transaction, simulated_tx) in transactions
+                .iter_mut()
+                .zip(simulated_bundle.transactions.iter())
+            {
+                if let BundleTransactionSource::Own { request, .. } = transaction {
+                    let gas_limit = policy.apply(simulated_tx.gas_used);
+                    println!(
+                        "Gas limit for {:?}: {} (simulation used {})",
+                        simulated_tx.hash, gas_limit, simulated_tx.gas_used
+                    );
+                    **request = request.as_ref().clone().gas(gas_limit);
+                }
+            }
+            gas_tightened = true;
+
+            bundle = sign_bundle(
+                &transactions,
+                target_block,
+                block_number,
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            )
+            .await?;
+        }
+
+        // Fan out to every builder at once, timing each one
+        let bundle = &bundle;
+        let submissions = join_all(client.0.iter().map(|(builder_url, builder)| async move {
+            let started = Instant::now();
+            let result = builder.send_bundle(bundle).await;
+            (builder_url, started.elapsed(), result)
+        }))
+        .await;
+
+        let mut report = SubmissionReport::new(target_block.as_u64(), attempt);
+        let mut accepted_bundle = None;
+
+        for (builder_url, latency, result) in submissions {
+            let error = match result {
+                Ok(responses) => match responses.into_iter().next() {
+                    Some(Ok(pending_bundle)) => {
+                        accepted_bundle.get_or_insert(pending_bundle);
+                        None
                     }
-                };
+                    Some(Err(e)) => Some(e.to_string()),
+                    None => Some("No response from builder".to_string()),
+                },
+                Err(e) => Some(e.to_string()),
+            };
+
+            report.record(builder_url.as_str(), error, latency);
+        }
+
+        if let Err(e) = report.log_and_write() {
+            eprintln!("Failed to write the bundle submission report: {}", e);
+        }
+
+        // Every builder got the same transactions, so the first accepted
+        // submission tells us whether the bundle landed.
+        let pending_bundle = match accepted_bundle {
+            Some(pending_bundle) => pending_bundle,
+            None => {
+                println!(
+                    "No builder accepted the bundle for block {} (attempt {}/{})",
+                    target_block, attempt, block_budget
+                );
+                // Let the target block pass before re-targeting
+                wait_for_block(provider, target_block).await?;
+                continue;
+            }
+        };
+
+        let transaction_hashes = pending_bundle.transactions.clone();
+
+        match pending_bundle.await {
+            Ok(_) => {
+                sp.stop_with_message(format!(
+                    "Bundle included in block {}: {:?}",
+                    target_block, transaction_hashes
+                ));
+                return Ok(());
+            }
+            Err(PendingBundleError::BundleNotIncluded) => {
+                println!(
+                    "Bundle not included in block {} (attempt {}/{})",
+                    target_block, attempt, block_budget
+                );
             }
             Err(e) => {
-                // Error Return
                 sp.stop();
-                return Err(eyre::eyre!("Failed to send the bundle: {}", e));
+                return Err(eyre::eyre!("Failed to track the bundle: {}", e));
             }
         }
     }
 
-    Ok(())
+    sp.stop();
+    Err(eyre::eyre!(
+        "Bundle was not included within {} blocks",
+        block_budget
+    ))
+}
+
+/// Polls until the chain has mined `block_number`.
+async fn wait_for_block(provider: &Provider<Http>, block_number: U64) -> eyre::Result<()> {
+    loop {
+        let current = provider
+            .get_block_number()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get the current block number: {}", e))?;
+        if current >= block_number {
+            return Ok(());
+        }
+
+        tokio::time::sleep(provider.get_interval()).await;
+    }
 }
@@ -1,13 +1,20 @@
 use ethers_flashbots::{BundleRequest, PendingBundleError};
 use rlp;
+use spinners::{Spinner, Spinners};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 
 use crate::core::contracts::{
-    deadline_timestamp, load_client_middleware, load_uniswap_v2_mempool, WETH_ADDRESS,
+    apply_slippage, deadline_timestamp, load_client_middleware, load_erc20_contract,
+    load_uniswap_v2_mempool, resolve_sell_amount, BundleClient, SellAmount,
 };
+use crate::core::dry_run::{dry_run_transactions, DryRunStep};
+use crate::core::gas::{estimate_fees, SendSettings};
+use crate::core::private_txn::{dry_run_bundle, BundleTransactionSource};
+use crate::core::router::{quote_buy, quote_sell, V2Trade};
+use crate::core::v3::{v3_buy_request, v3_sell_request, V3Trade};
 use crate::env::provider::http_node_endpoint;
 
 use ethers::prelude::*;
@@ -21,28 +28,44 @@ use tokio::join;
 pub const MAX_ATTEMPTS: usize = 5;
 pub const INITIAL_DELAY: Duration = Duration::from_secs(5);
 pub const MAX_DELAY: Duration = Duration::from_secs(60);
+pub const APPROVE_GAS_LIMIT: u64 = 100_000;
 
 pub async fn uniswap_v2_transaction(
     value: U256,
-    token_address: &str,
-    slippage_percentage: f64,
-    buy_extra_gas: U256,
-    miner_tip: U256,
     maxbuy_amount: U256,
+    trade: &V2Trade<'_>,
     wallet: &LocalWallet,
     provider: Arc<Provider<Http>>,
+    simulation_client: Option<&BundleClient>,
+    send: &SendSettings<'_>,
 ) -> eyre::Result<()> {
+    let V2Trade {
+        token_address,
+        slippage_percentage,
+        dex,
+        ..
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
+
+    let tokenaddress = H160::from_str(token_address)?;
+
     let (nonce_result, gas_details_result, uniswap_v2_contract_result, client_result) = join!(
         provider.get_transaction_count(wallet.address(), None),
-        provider.estimate_eip1559_fees(None),
-        load_uniswap_v2_mempool(wallet, provider.clone()),
-        load_client_middleware(&wallet, &wallet, provider.clone()),
+        estimate_fees(provider.as_ref(), gas_model),
+        load_uniswap_v2_mempool(dex.router, wallet, provider.clone()),
+        load_client_middleware(wallet, wallet, provider.clone()),
     );
 
     let nonce = nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
 
     let (max_fee_per_gas, max_priority_fee_per_gas) =
-        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate EIP-1559 fees"))?;
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
 
     let uniswap_v2_contract = uniswap_v2_contract_result.or_else(|e| {
         Err(eyre::eyre!(
@@ -54,93 +77,479 @@ pub async fn uniswap_v2_transaction(
     let client =
         client_result.or_else(|e| Err(eyre::eyre!("Failed to load client middleware: {}", e)))?;
 
-    // Run the asynchronous operations in parallel
+    let quote = quote_buy(
+        value,
+        maxbuy_amount,
+        trade,
+        &uniswap_v2_contract,
+        provider.clone(),
+    )
+    .await?;
+    let path = quote.path;
+    let value_to_use = quote.amount_in;
+    let last_token_value = quote.amount_out;
+    if last_token_value.is_zero() {
+        return Err(eyre::eyre!("No liquidity to buy {} against", token_address));
+    }
 
-    let tokenaddress = H160::from_str(token_address)?;
-    let path = vec![*WETH_ADDRESS, tokenaddress];
+    let amount_out_tokens = apply_slippage(last_token_value, slippage_percentage)?;
 
-    let get_input_ether_method = uniswap_v2_contract
-        .method::<_, Vec<U256>>("getAmountsIn", (maxbuy_amount, path.clone()))
+    let to = wallet.address();
+
+    let call_data = uniswap_v2_contract
+        .method::<_, H160>(
+            "swapExactETHForTokensSupportingFeeOnTransferTokens",
+            (amount_out_tokens, path.clone(), to, deadline_timestamp()),
+        )
         .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
 
-    let get_input_ether_result = get_input_ether_method.call().await;
+    let transaction_data = call_data
+        .calldata()
+        .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
 
-    let get_input_ether = get_input_ether_result
-        .map_err(|e| eyre::eyre!("Failed to get amount of Intokens: {}", e))?;
+    println!("Transaction data: {:?}", transaction_data);
+    let txn_request = Eip1559TransactionRequest::new()
+        .from(wallet.address())
+        .to(uniswap_v2_contract.address())
+        .value(value_to_use)
+        .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+        .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+        .data(transaction_data)
+        .nonce(nonce);
 
-    let value_to_use = if value > get_input_ether[0] {
-        get_input_ether[0]
-    } else {
-        value
-    };
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    if dry_run {
+        let step = DryRunStep::Buy {
+            token: tokenaddress,
+            amount_in: value_to_use,
+            expected_out: Some(last_token_value),
+        };
+        return simulate_own(
+            vec![(step, txn_request)],
+            wallet,
+            provider,
+            simulation_client,
+            send,
+        )
+        .await;
+    }
 
-    let get_output_tokens_method = uniswap_v2_contract
-        .method::<_, Vec<U256>>("getAmountsOut", (value_to_use, path.clone()))
-        .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
+    // Send the transaction
+    let tx_hash = client
+        .send_transaction(txn_request, None)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to send transaction: {}", e))?;
 
-    let get_output_tokens_result = get_output_tokens_method.call().await;
+    // Extract the transaction hash from the PendingTransaction
+    wait_for_receipt(&provider, tx_hash.tx_hash()).await
+}
 
-    let get_output_tokens = get_output_tokens_result
-        .map_err(|e| eyre::eyre!("Failed to get amount of Outtokens: {}", e))?;
+pub async fn uniswap_v2_sell_transaction(
+    sell_amount: SellAmount,
+    trade: &V2Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    simulation_client: Option<&BundleClient>,
+    send: &SendSettings<'_>,
+) -> eyre::Result<()> {
+    let V2Trade {
+        token_address,
+        slippage_percentage,
+        dex,
+        ..
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
 
-    // Ensure get_output_tokens is not empty
-    let last_token_value = match get_output_tokens.last() {
-        Some(value) => *value,
-        None => return Err(eyre::eyre!("get_output_tokens is empty")),
-    };
+    let tokenaddress = H160::from_str(token_address)?;
 
-    // Calculate the slippage adjustment
-    let slippage_multiplier = U256::from((slippage_percentage * 1e18) as u128);
-    let slippage_adjustment = last_token_value
-        .checked_mul(slippage_multiplier)
-        .and_then(|result| result.checked_div(U256::from(1_000_000_000_000_000_000u128)))
-        .ok_or_else(|| eyre::eyre!("Overflow occurred during slippage adjustment calculation"))?;
+    let (
+        nonce_result,
+        gas_details_result,
+        uniswap_v2_contract_result,
+        erc20_contract_result,
+        client_result,
+    ) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+        load_uniswap_v2_mempool(dex.router, wallet, provider.clone()),
+        load_erc20_contract(tokenaddress, wallet, provider.clone()),
+        load_client_middleware(wallet, wallet, provider.clone()),
+    );
 
-    let amount_out_tokens = last_token_value
-        .checked_sub(slippage_adjustment)
-        .ok_or_else(|| eyre::eyre!("Overflow occurred during amount out tokens calculation"))?;
+    let mut nonce =
+        nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
 
-    let to = wallet.address();
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let uniswap_v2_contract = uniswap_v2_contract_result
+        .map_err(|e| eyre::eyre!("Failed to load uniswap v2 mempool contract: {}", e))?;
+
+    let erc20_contract =
+        erc20_contract_result.map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
+
+    let client =
+        client_result.map_err(|e| eyre::eyre!("Failed to load client middleware: {}", e))?;
+
+    let amount_in = resolve_sell_amount(&erc20_contract, wallet.address(), sell_amount).await?;
+
+    if amount_in.is_zero() {
+        return Err(eyre::eyre!("No {} balance to sell", token_address));
+    }
+
+    // With `dry_run`, the approval is simulated with the swap instead of sent
+    let mut dry_run_steps = Vec::new();
+
+    let allowance = erc20_contract
+        .method::<_, U256>(
+            "allowance",
+            (wallet.address(), uniswap_v2_contract.address()),
+        )
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get token allowance: {}", e))?;
+
+    if allowance < amount_in {
+        let approve_data = erc20_contract
+            .method::<_, bool>("approve", (uniswap_v2_contract.address(), U256::MAX))
+            .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+            .calldata()
+            .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+        let approve_request = Eip1559TransactionRequest::new()
+            .from(wallet.address())
+            .to(erc20_contract.address())
+            .gas(U256::from(APPROVE_GAS_LIMIT))
+            .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+            .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+            .data(approve_data)
+            .nonce(nonce);
+
+        if dry_run {
+            dry_run_steps.push((DryRunStep::Other("approve"), approve_request));
+        } else {
+            let approve_tx = client
+                .send_transaction(approve_request, None)
+                .await
+                .map_err(|e| eyre::eyre!("Failed to send approve transaction: {}", e))?;
+
+            println!("Approving router to spend {}", token_address);
+            wait_for_receipt(&provider, approve_tx.tx_hash()).await?;
+        }
+
+        nonce += U256::one();
+    }
+
+    let quote = quote_sell(amount_in, trade, &uniswap_v2_contract, provider.clone()).await?;
+    let path = quote.path;
+    let last_ether_value = quote.amount_out;
+    if last_ether_value.is_zero() {
+        return Err(eyre::eyre!(
+            "No liquidity to sell {} against",
+            token_address
+        ));
+    }
+
+    let amount_out_ether = apply_slippage(last_ether_value, slippage_percentage)?;
 
     let call_data = uniswap_v2_contract
-        .method::<_, H160>(
-            "swapExactETHForTokensSupportingFeeOnTransferTokens",
-            (amount_out_tokens, path.clone(), to, deadline_timestamp()),
+        .method::<_, ()>(
+            "swapExactTokensForETHSupportingFeeOnTransferTokens",
+            (
+                amount_in,
+                amount_out_ether,
+                path.clone(),
+                wallet.address(),
+                deadline_timestamp(),
+            ),
         )
-        .or_else(|_| Err(eyre::eyre!("Uniswap V2 Router contract method not found")))?;
+        .map_err(|_| eyre::eyre!("Uniswap V2 Router contract method not found"))?;
 
     let transaction_data = call_data
         .calldata()
         .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
-
-    println!("Transaction data: {:?}", transaction_data);
-
-    let estimated_gas = U256::from(313252);
-    // // // Constructing the EIP1559 transaction
     let txn_request = Eip1559TransactionRequest::new()
         .from(wallet.address())
         .to(uniswap_v2_contract.address())
-        .value(value_to_use)
-        .gas(estimated_gas)
         .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
         .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
         .data(transaction_data)
         .nonce(nonce);
 
-    // Send the transaction
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    if dry_run {
+        let step = DryRunStep::Sell {
+            token: tokenaddress,
+            amount_in,
+            expected_out: Some(last_ether_value),
+        };
+        dry_run_steps.push((step, txn_request));
+        return simulate_own(dry_run_steps, wallet, provider, simulation_client, send).await;
+    }
+
     let tx_hash = client
         .send_transaction(txn_request, None)
         .await
         .map_err(|e| eyre::eyre!("Failed to send transaction: {}", e))?;
 
-    // Extract the transaction hash from the PendingTransaction
-    let pending_tx_hash = tx_hash.tx_hash();
+    wait_for_receipt(&provider, tx_hash.tx_hash()).await
+}
+
+pub async fn uniswap_v3_transaction(
+    value: U256,
+    trade: &V3Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    simulation_client: Option<&BundleClient>,
+    send: &SendSettings<'_>,
+) -> eyre::Result<()> {
+    let V3Trade {
+        token_address,
+        slippage_percentage,
+        route_config,
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
+
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (nonce_result, gas_details_result, client_result, request_result) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+        load_client_middleware(wallet, wallet, provider.clone()),
+        v3_buy_request(
+            value,
+            tokenaddress,
+            slippage_percentage,
+            route_config,
+            wallet.address(),
+            provider.clone(),
+        ),
+    );
+
+    let nonce = nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let client =
+        client_result.map_err(|e| eyre::eyre!("Failed to load client middleware: {}", e))?;
+
+    let txn_request = request_result?
+        .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+        .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+        .nonce(nonce);
+
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    if dry_run {
+        let step = DryRunStep::Buy {
+            token: tokenaddress,
+            amount_in: value,
+            expected_out: None,
+        };
+        return simulate_own(
+            vec![(step, txn_request)],
+            wallet,
+            provider,
+            simulation_client,
+            send,
+        )
+        .await;
+    }
+
+    let tx_hash = client
+        .send_transaction(txn_request, None)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to send transaction: {}", e))?;
+
+    wait_for_receipt(&provider, tx_hash.tx_hash()).await
+}
+
+pub async fn uniswap_v3_sell_transaction(
+    sell_amount: SellAmount,
+    trade: &V3Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    simulation_client: Option<&BundleClient>,
+    send: &SendSettings<'_>,
+) -> eyre::Result<()> {
+    let V3Trade {
+        token_address,
+        slippage_percentage,
+        route_config,
+    } = *trade;
+    let SendSettings {
+        buy_extra_gas,
+        miner_tip,
+        gas_policy,
+        gas_model,
+        dry_run,
+    } = *send;
+
+    let tokenaddress = H160::from_str(token_address)?;
+
+    let (nonce_result, gas_details_result, erc20_contract_result, client_result) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+        load_erc20_contract(tokenaddress, wallet, provider.clone()),
+        load_client_middleware(wallet, wallet, provider.clone()),
+    );
+
+    let mut nonce =
+        nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let erc20_contract =
+        erc20_contract_result.map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
+
+    let client =
+        client_result.map_err(|e| eyre::eyre!("Failed to load client middleware: {}", e))?;
+
+    let amount_in = resolve_sell_amount(&erc20_contract, wallet.address(), sell_amount).await?;
+
+    if amount_in.is_zero() {
+        return Err(eyre::eyre!("No {} balance to sell", token_address));
+    }
+
+    // With `dry_run`, the approval is simulated with the swap instead of sent
+    let mut dry_run_steps = Vec::new();
+
+    let allowance = erc20_contract
+        .method::<_, U256>(
+            "allowance",
+            (wallet.address(), route_config.deployment.swap_router_02),
+        )
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get token allowance: {}", e))?;
+
+    if allowance < amount_in {
+        let approve_data = erc20_contract
+            .method::<_, bool>(
+                "approve",
+                (route_config.deployment.swap_router_02, U256::MAX),
+            )
+            .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+            .calldata()
+            .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+        let approve_request = Eip1559TransactionRequest::new()
+            .from(wallet.address())
+            .to(erc20_contract.address())
+            .gas(U256::from(APPROVE_GAS_LIMIT))
+            .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+            .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+            .data(approve_data)
+            .nonce(nonce);
+
+        if dry_run {
+            dry_run_steps.push((DryRunStep::Other("approve"), approve_request));
+        } else {
+            let approve_tx = client
+                .send_transaction(approve_request, None)
+                .await
+                .map_err(|e| eyre::eyre!("Failed to send approve transaction: {}", e))?;
+
+            println!("Approving V3 router to spend {}", token_address);
+            wait_for_receipt(&provider, approve_tx.tx_hash()).await?;
+        }
+
+        nonce += U256::one();
+    }
+
+    let txn_request = v3_sell_request(
+        amount_in,
+        tokenaddress,
+        slippage_percentage,
+        route_config,
+        wallet.address(),
+        provider.clone(),
+    )
+    .await?
+    .max_priority_fee_per_gas(max_priority_fee_per_gas + miner_tip)
+    .max_fee_per_gas(max_fee_per_gas + buy_extra_gas)
+    .nonce(nonce);
+
+    let estimated_gas = gas_policy.estimate(provider.as_ref(), &txn_request).await;
+    let txn_request = txn_request.gas(estimated_gas);
+
+    if dry_run {
+        let step = DryRunStep::Sell {
+            token: tokenaddress,
+            amount_in,
+            expected_out: None,
+        };
+        dry_run_steps.push((step, txn_request));
+        return simulate_own(dry_run_steps, wallet, provider, simulation_client, send).await;
+    }
+
+    let tx_hash = client
+        .send_transaction(txn_request, None)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to send transaction: {}", e))?;
+
+    wait_for_receipt(&provider, tx_hash.tx_hash()).await
+}
+
+/// Simulates one wallet's `transactions` for a dry run. With a relay
+/// `simulation_client` they run in order as one bundle, the same way private
+/// dry runs do, so a swap sees the approval before it and buys are measured
+/// with balance probes. Without one each goes through `eth_call` on its own.
+async fn simulate_own(
+    transactions: Vec<(DryRunStep, Eip1559TransactionRequest)>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    simulation_client: Option<&BundleClient>,
+    send: &SendSettings<'_>,
+) -> eyre::Result<()> {
+    let Some(client) = simulation_client else {
+        return dry_run_transactions(transactions, wallet, provider).await;
+    };
+
+    let (steps, bundle): (Vec<_>, Vec<_>) = transactions
+        .into_iter()
+        .map(|(step, request)| {
+            let transaction = BundleTransactionSource::Own {
+                wallet: wallet.clone(),
+                request: Box::new(request),
+            };
+            (step, transaction)
+        })
+        .unzip();
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Simulating transactions...".into());
+    dry_run_bundle(client, provider, &bundle, &steps, send, &mut sp).await
+}
 
+/// Polls for `tx_hash`'s receipt with exponential backoff and fails if the
+/// transaction reverted or never got mined.
+pub async fn wait_for_receipt(provider: &Provider<Http>, tx_hash: H256) -> eyre::Result<()> {
     // Check if the swap transaction was successful
     let mut delay = INITIAL_DELAY;
 
     let receipt = loop {
-        let r = provider.get_transaction_receipt(pending_tx_hash).await;
+        let r = provider.get_transaction_receipt(tx_hash).await;
 
         match &r {
             Ok(Some(_)) => break r,
@@ -161,7 +570,7 @@ pub async fn uniswap_v2_transaction(
         }
     };
 
-    let receipt = receipt.unwrap();
+    let receipt = receipt.map_err(|e| eyre::eyre!("Failed to fetch transaction receipt: {}", e))?;
 
     match receipt {
         Some(receipt) => match receipt.status {
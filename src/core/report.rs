@@ -0,0 +1,88 @@
+use log::info;
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+/// File every bundle submission report is appended to, one JSON object per line.
+pub const SUBMISSION_REPORT_PATH: &str = "./bundle_submissions.jsonl";
+
+/// Outcome of sending one bundle to one builder.
+#[derive(Debug, Serialize)]
+pub struct BuilderSubmission {
+    pub builder: String,
+    pub accepted: bool,
+    pub error: Option<String>,
+    pub latency_ms: u128,
+}
+
+/// Per-builder outcome of one bundle submission.
+#[derive(Debug, Serialize)]
+pub struct SubmissionReport {
+    pub timestamp: u64,
+    pub target_block: u64,
+    pub attempt: u64,
+    pub builders: Vec<BuilderSubmission>,
+}
+
+impl SubmissionReport {
+    pub fn new(target_block: u64, attempt: u64) -> Self {
+        SubmissionReport {
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_secs(),
+            target_block,
+            attempt,
+            builders: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, builder: &str, error: Option<String>, latency: Duration) {
+        self.builders.push(BuilderSubmission {
+            builder: builder.to_string(),
+            accepted: error.is_none(),
+            error,
+            latency_ms: latency.as_millis(),
+        });
+    }
+
+    pub fn accepted(&self) -> usize {
+        self.builders.iter().filter(|b| b.accepted).count()
+    }
+
+    /// Logs a one-line summary plus one line per builder, then appends the
+    /// report to [`SUBMISSION_REPORT_PATH`].
+    pub fn log_and_write(&self) -> eyre::Result<()> {
+        info!(
+            "Bundle for block {} (attempt {}): {}/{} builders accepted",
+            self.target_block,
+            self.attempt,
+            self.accepted(),
+            self.builders.len()
+        );
+
+        for submission in self.builders.iter() {
+            match &submission.error {
+                None => info!(
+                    "  {} accepted in {}ms",
+                    submission.builder, submission.latency_ms
+                ),
+                Some(error) => info!(
+                    "  {} rejected in {}ms: {}",
+                    submission.builder, submission.latency_ms, error
+                ),
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(SUBMISSION_REPORT_PATH)?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use ethers::prelude::*;
+use ethers::types::{H160, U256};
+use futures::future::join_all;
+use log::debug;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::core::chain::V2Deployment;
+use crate::core::contracts::{load_pair_address_between, ConfigContractmempool};
+use crate::core::pair::{TransferTax, UniswapV2Pair};
+
+/// What a V2 swap trades and where: the token, the slippage its minimum
+/// output allows, the token's transfer tax and the DEX it is routed on.
+#[derive(Debug, Clone, Copy)]
+pub struct V2Trade<'a> {
+    pub token_address: &'a str,
+    pub slippage_percentage: f64,
+    pub transfer_tax: TransferTax,
+    /// Fixed path in buy order; the best route is searched when unset.
+    pub route_override: Option<&'a [H160]>,
+    pub dex: &'a V2Deployment,
+    pub weth: H160,
+    /// Tokens tried as the middle hop when searching for the best route.
+    pub route_intermediates: &'a [H160],
+}
+
+/// A V2 path and what it is expected to pay out.
+#[derive(Debug, Clone)]
+pub struct V2Quote {
+    pub path: Vec<H160>,
+    /// Input actually spent, after any max-buy clipping.
+    pub amount_in: U256,
+    /// Output reaching the wallet, after transfer taxes.
+    pub amount_out: U256,
+}
+
+/// Router `getAmountsOut` along `path`, or zero when the path cannot be
+/// swapped.
+async fn amounts_out(router: &ConfigContractmempool, amount_in: U256, path: &[H160]) -> U256 {
+    let amounts = match router.method::<_, Vec<U256>>("getAmountsOut", (amount_in, path.to_vec())) {
+        Ok(call) => call.call().await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+
+    amounts.last().cloned().unwrap_or_default()
+}
+
+/// Candidate paths from `token_in` to `token_out`: the direct pair and one
+/// hop through each of `intermediates`, keeping only those whose pairs all
+/// exist on `factory`.
+async fn candidate_paths(
+    token_in: H160,
+    token_out: H160,
+    intermediates: &[H160],
+    factory: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<Vec<Vec<H160>>> {
+    let mut candidates = vec![vec![token_in, token_out]];
+    candidates.extend(
+        intermediates
+            .iter()
+            .filter(|middle| **middle != token_in && **middle != token_out)
+            .map(|middle| vec![token_in, *middle, token_out]),
+    );
+
+    let existing = join_all(candidates.into_iter().map(|path| {
+        let provider = provider.clone();
+        async move {
+            for hop in path.windows(2) {
+                let pair =
+                    load_pair_address_between(hop[0], hop[1], factory, provider.clone()).await?;
+                if pair.is_zero() {
+                    return Ok(None);
+                }
+            }
+            Ok(Some(path))
+        }
+    }))
+    .await;
+
+    existing
+        .into_iter()
+        .filter_map(|result: eyre::Result<Option<Vec<H160>>>| result.transpose())
+        .collect()
+}
+
+/// The path from `token_in` to `token_out`, direct or through one of
+/// `intermediates`, with the largest `getAmountsOut` for `amount_in`,
+/// together with that amount.
+pub async fn find_best_route(
+    amount_in: U256,
+    token_in: H160,
+    token_out: H160,
+    intermediates: &[H160],
+    factory: H160,
+    router: &ConfigContractmempool,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<(Vec<H160>, U256)> {
+    let candidates = candidate_paths(token_in, token_out, intermediates, factory, provider).await?;
+
+    let quotes = join_all(candidates.into_iter().map(|path| async move {
+        let amount_out = amounts_out(router, amount_in, &path).await;
+        (path, amount_out)
+    }))
+    .await;
+
+    for (path, amount_out) in quotes.iter() {
+        debug!("Route {:?}: {}", path, amount_out);
+    }
+
+    quotes
+        .into_iter()
+        .filter(|(_, amount_out)| !amount_out.is_zero())
+        .max_by_key(|(_, amount_out)| *amount_out)
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "No V2 route from {:?} to {:?} with liquidity",
+                token_in,
+                token_out
+            )
+        })
+}
+
+/// Quotes buying the trade's token with `value` wei. The direct WETH pair is
+/// quoted locally from its reserves (clipping the input to `maxbuy_amount`
+/// tokens); multi-hop routes go through the router.
+pub async fn quote_buy(
+    value: U256,
+    maxbuy_amount: U256,
+    trade: &V2Trade<'_>,
+    router: &ConfigContractmempool,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<V2Quote> {
+    let V2Trade {
+        transfer_tax,
+        route_override,
+        dex,
+        weth,
+        route_intermediates,
+        ..
+    } = *trade;
+    let token = H160::from_str(trade.token_address)?;
+
+    let path = match route_override {
+        Some(path) => path.to_vec(),
+        None => {
+            find_best_route(
+                value,
+                weth,
+                token,
+                route_intermediates,
+                dex.factory,
+                router,
+                provider.clone(),
+            )
+            .await?
+            .0
+        }
+    };
+
+    if path.len() > 2 {
+        let amount_out = transfer_tax.bought(amounts_out(router, value, &path).await);
+        return Ok(V2Quote {
+            path,
+            amount_in: value,
+            amount_out,
+        });
+    }
+
+    let pair = UniswapV2Pair::load(token, dex, weth, provider)
+        .await?
+        .with_transfer_tax(transfer_tax);
+
+    let mut amount_in = value;
+    if !maxbuy_amount.is_zero() {
+        if let Some(max_input) = pair.quote_buy_exact_out(maxbuy_amount) {
+            amount_in = std::cmp::min(value, max_input);
+        }
+    }
+
+    Ok(V2Quote {
+        path,
+        amount_in,
+        amount_out: pair.quote_buy(amount_in),
+    })
+}
+
+/// Quotes selling `amount_in` of the trade's token for ETH, locally for the
+/// direct WETH pair and through the router for multi-hop routes. A
+/// `route_override` is given in buy order and reversed here.
+pub async fn quote_sell(
+    amount_in: U256,
+    trade: &V2Trade<'_>,
+    router: &ConfigContractmempool,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<V2Quote> {
+    let V2Trade {
+        transfer_tax,
+        route_override,
+        dex,
+        weth,
+        route_intermediates,
+        ..
+    } = *trade;
+    let token = H160::from_str(trade.token_address)?;
+
+    let path = match route_override {
+        Some(path) => path.iter().rev().cloned().collect(),
+        None => {
+            find_best_route(
+                transfer_tax.sold(amount_in),
+                token,
+                weth,
+                route_intermediates,
+                dex.factory,
+                router,
+                provider.clone(),
+            )
+            .await?
+            .0
+        }
+    };
+
+    let amount_out = if path.len() > 2 {
+        amounts_out(router, transfer_tax.sold(amount_in), &path).await
+    } else {
+        UniswapV2Pair::load(token, dex, weth, provider)
+            .await?
+            .with_transfer_tax(transfer_tax)
+            .quote_sell(amount_in)
+    };
+
+    Ok(V2Quote {
+        path,
+        amount_in,
+        amount_out,
+    })
+}
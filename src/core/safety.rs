@@ -0,0 +1,432 @@
+use ethers::prelude::*;
+use ethers::types::{Bytes, H160, U256};
+use ethers_core::types::Eip1559TransactionRequest;
+use ethers_flashbots::{BundleRequest, SimulatedTransaction};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::join;
+
+use crate::core::chain::GasModel;
+use crate::core::contracts::{
+    deadline_timestamp, load_erc20_contract, load_uniswap_v2_mempool, pair_for, BundleClient,
+    UNISWAP_V2_PAIR,
+};
+use crate::core::gas::estimate_fees;
+use crate::core::pair::{get_amount_out, TransferTax, BPS};
+use crate::core::private_txn::sign_wallet_transaction;
+use crate::core::public_txn::APPROVE_GAS_LIMIT;
+use crate::core::router::V2Trade;
+
+/// Gas limit for the read-only probe transactions (`getReserves`,
+/// `balanceOf`); they are only ever simulated.
+pub const PROBE_READ_GAS_LIMIT: u64 = 100_000;
+pub const PROBE_SWAP_GAS_LIMIT: u64 = 1_000_000;
+
+/// Outcome of a simulated buy followed by an immediate sell.
+#[derive(Debug, Clone)]
+pub struct TokenSafetyReport {
+    /// Share of the expected buy output that never arrived, `0.05` is 5%.
+    pub buy_tax: f64,
+    /// Share of the sold tokens that never reached the pair.
+    pub sell_tax: f64,
+    /// Step and revert reason when the buy, approve or sell failed.
+    pub transfer_failure: Option<String>,
+}
+
+impl From<&TokenSafetyReport> for TransferTax {
+    fn from(report: &TokenSafetyReport) -> Self {
+        let to_bps = |tax: f64| (tax.clamp(0.0, 1.0) * BPS as f64).ceil() as u64;
+
+        TransferTax {
+            buy_bps: to_bps(report.buy_tax),
+            sell_bps: to_bps(report.sell_tax),
+        }
+    }
+}
+
+impl TokenSafetyReport {
+    /// Checks the report against the configured tax ceilings.
+    pub fn check(&self, max_buy_tax: f64, max_sell_tax: f64) -> eyre::Result<()> {
+        if let Some(failure) = &self.transfer_failure {
+            return Err(eyre::eyre!("Token failed the safety check: {}", failure));
+        }
+
+        if self.buy_tax > max_buy_tax {
+            return Err(eyre::eyre!(
+                "Buy tax {:.2}% exceeds the {:.2}% limit",
+                self.buy_tax * 100.0,
+                max_buy_tax * 100.0
+            ));
+        }
+
+        if self.sell_tax > max_sell_tax {
+            return Err(eyre::eyre!(
+                "Sell tax {:.2}% exceeds the {:.2}% limit",
+                self.sell_tax * 100.0,
+                max_sell_tax * 100.0
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Simulates, with `simulate_bundle`, a buy of `probe_value` wei of the
+/// trade's token on its DEX's WETH pair from `wallet` followed by selling
+/// everything it received. Nothing is broadcast.
+///
+/// The probe bundle reads the pair reserves and the wallet's token balance
+/// between the steps, so the taxes come from the `eth_callBundle` return
+/// values: buy tax compares the tokens received with the reserve-based
+/// quote, sell tax compares the tokens sold with what actually reached the
+/// pair. Tokens that swap their own fees back through the pair during a sell
+/// can make the sell tax look lower than it is.
+///
+/// `foreign_txs` run first, e.g. a pending `addLiquidityETH` when the pair
+/// does not exist on chain yet.
+pub async fn analyze_token(
+    foreign_txs: Vec<Bytes>,
+    probe_value: U256,
+    trade: &V2Trade<'_>,
+    wallet: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+    client: &BundleClient,
+    gas_model: GasModel,
+) -> eyre::Result<TokenSafetyReport> {
+    let V2Trade {
+        token_address,
+        dex,
+        weth,
+        ..
+    } = *trade;
+
+    let tokenaddress = H160::from_str(token_address)?;
+    let pair_address = pair_for(dex, weth, tokenaddress);
+
+    let (
+        nonce_result,
+        gas_details_result,
+        block_number_result,
+        uniswap_v2_contract_result,
+        erc20_contract_result,
+    ) = join!(
+        provider.get_transaction_count(wallet.address(), None),
+        estimate_fees(provider.as_ref(), gas_model),
+        provider.get_block_number(),
+        load_uniswap_v2_mempool(dex.router, wallet, provider.clone()),
+        load_erc20_contract(tokenaddress, wallet, provider.clone()),
+    );
+
+    let mut nonce =
+        nonce_result.map_err(|e| eyre::eyre!("Failed to get transaction count: {}", e))?;
+
+    let (max_fee_per_gas, _) =
+        gas_details_result.map_err(|_| eyre::eyre!("Failed to estimate gas fees"))?;
+
+    let block_number = block_number_result
+        .map_err(|e| eyre::eyre!("Failed to get the current block number: {}", e))?;
+
+    let uniswap_v2_contract = uniswap_v2_contract_result
+        .map_err(|e| eyre::eyre!("Failed to load uniswap v2 mempool contract: {}", e))?;
+
+    let erc20_contract =
+        erc20_contract_result.map_err(|e| eyre::eyre!("Failed to load ERC20 contract: {}", e))?;
+
+    let pair_abi = ethabi::Contract::load(UNISWAP_V2_PAIR.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v2 pair contract ABI: {}", e))?;
+    let get_reserves = pair_abi
+        .function("getReserves")
+        .map_err(|e| eyre::eyre!("Uniswap V2 Pair contract method not found: {}", e))?;
+    let get_reserves_data: Bytes = get_reserves
+        .encode_input(&[])
+        .map_err(|e| eyre::eyre!("Failed to encode getReserves: {}", e))?
+        .into();
+
+    let balance_of_data = erc20_contract
+        .method::<_, U256>("balanceOf", wallet.address())
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .calldata()
+        .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+    let buy_data = uniswap_v2_contract
+        .method::<_, ()>(
+            "swapExactETHForTokensSupportingFeeOnTransferTokens",
+            (
+                U256::zero(),
+                vec![weth, tokenaddress],
+                wallet.address(),
+                deadline_timestamp(),
+            ),
+        )
+        .map_err(|_| eyre::eyre!("Uniswap V2 Router contract method not found"))?
+        .calldata()
+        .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+    let approve_data = erc20_contract
+        .method::<_, bool>("approve", (uniswap_v2_contract.address(), U256::MAX))
+        .map_err(|_| eyre::eyre!("ERC20 contract method not found"))?
+        .calldata()
+        .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+    // Probe transactions pay no tip; they are only ever simulated
+    let probe = |to: H160, value: U256, data: Bytes, gas: u64, nonce: U256| {
+        Eip1559TransactionRequest::new()
+            .to(to)
+            .value(value)
+            .data(data)
+            .gas(gas)
+            .max_priority_fee_per_gas(U256::zero())
+            .max_fee_per_gas(max_fee_per_gas)
+            .nonce(nonce)
+    };
+
+    // First pass: reserves, balance, buy, balance, approve. Gives the amount
+    // bought, which the sell in the second pass needs.
+    let mut steps = vec![
+        probe(
+            pair_address,
+            U256::zero(),
+            get_reserves_data.clone(),
+            PROBE_READ_GAS_LIMIT,
+            nonce,
+        ),
+        probe(
+            tokenaddress,
+            U256::zero(),
+            balance_of_data.clone(),
+            PROBE_READ_GAS_LIMIT,
+            nonce + 1,
+        ),
+        probe(
+            uniswap_v2_contract.address(),
+            probe_value,
+            buy_data,
+            PROBE_SWAP_GAS_LIMIT,
+            nonce + 2,
+        ),
+        probe(
+            tokenaddress,
+            U256::zero(),
+            balance_of_data.clone(),
+            PROBE_READ_GAS_LIMIT,
+            nonce + 3,
+        ),
+        probe(
+            tokenaddress,
+            U256::zero(),
+            approve_data,
+            APPROVE_GAS_LIMIT,
+            nonce + 4,
+        ),
+    ];
+    nonce += U256::from(steps.len());
+
+    let first_pass = simulate_probe(&foreign_txs, &steps, wallet, block_number, client).await?;
+    let offset = foreign_txs.len();
+
+    if let Some(failure) = probe_failure(&first_pass[offset + 2], "buy")
+        .or_else(|| probe_failure(&first_pass[offset + 4], "approve"))
+    {
+        return Ok(TokenSafetyReport {
+            buy_tax: 1.0,
+            sell_tax: 1.0,
+            transfer_failure: Some(failure),
+        });
+    }
+
+    let (reserve_weth, reserve_token) =
+        decode_reserves(get_reserves, &first_pass[offset], weth, tokenaddress)?;
+    let balance_before = decode_uint(&first_pass[offset + 1])?;
+    let balance_after_buy = decode_uint(&first_pass[offset + 3])?;
+
+    let expected = get_amount_out(probe_value, reserve_weth, reserve_token, dex.fee_bps);
+    let received = balance_after_buy.saturating_sub(balance_before);
+    let buy_tax = tax(expected, received);
+
+    if received.is_zero() {
+        return Ok(TokenSafetyReport {
+            buy_tax,
+            sell_tax: 1.0,
+            transfer_failure: Some("buy: no tokens received".to_string()),
+        });
+    }
+
+    // Second pass: the same steps, then reserves, sell what was received,
+    // reserves again.
+    let sell_data = uniswap_v2_contract
+        .method::<_, ()>(
+            "swapExactTokensForETHSupportingFeeOnTransferTokens",
+            (
+                received,
+                U256::zero(),
+                vec![tokenaddress, weth],
+                wallet.address(),
+                deadline_timestamp(),
+            ),
+        )
+        .map_err(|_| eyre::eyre!("Uniswap V2 Router contract method not found"))?
+        .calldata()
+        .ok_or_else(|| eyre::eyre!("Failed to get calldata"))?;
+
+    steps.push(probe(
+        pair_address,
+        U256::zero(),
+        get_reserves_data.clone(),
+        PROBE_READ_GAS_LIMIT,
+        nonce,
+    ));
+    steps.push(probe(
+        uniswap_v2_contract.address(),
+        U256::zero(),
+        sell_data,
+        PROBE_SWAP_GAS_LIMIT,
+        nonce + 1,
+    ));
+    steps.push(probe(
+        pair_address,
+        U256::zero(),
+        get_reserves_data,
+        PROBE_READ_GAS_LIMIT,
+        nonce + 2,
+    ));
+
+    let second_pass = simulate_probe(&foreign_txs, &steps, wallet, block_number, client).await?;
+
+    if let Some(failure) = probe_failure(&second_pass[offset + 6], "sell") {
+        return Ok(TokenSafetyReport {
+            buy_tax,
+            sell_tax: 1.0,
+            transfer_failure: Some(failure),
+        });
+    }
+
+    let (_, token_reserve_before_sell) =
+        decode_reserves(get_reserves, &second_pass[offset + 5], weth, tokenaddress)?;
+    let (_, token_reserve_after_sell) =
+        decode_reserves(get_reserves, &second_pass[offset + 7], weth, tokenaddress)?;
+
+    let reached_pair = token_reserve_after_sell.saturating_sub(token_reserve_before_sell);
+    let sell_tax = tax(received, reached_pair);
+
+    let report = TokenSafetyReport {
+        buy_tax,
+        sell_tax,
+        transfer_failure: None,
+    };
+
+    println!(
+        "Token safety: buy tax {:.2}%, sell tax {:.2}%",
+        report.buy_tax * 100.0,
+        report.sell_tax * 100.0
+    );
+
+    Ok(report)
+}
+
+async fn simulate_probe(
+    foreign_txs: &[Bytes],
+    steps: &[Eip1559TransactionRequest],
+    wallet: &LocalWallet,
+    block_number: U64,
+    client: &BundleClient,
+) -> eyre::Result<Vec<SimulatedTransaction>> {
+    let mut bundle = BundleRequest::new()
+        .set_block(block_number + 1)
+        .set_simulation_block(block_number)
+        .set_simulation_timestamp(0);
+
+    for foreign_tx in foreign_txs {
+        bundle = bundle.push_transaction(foreign_tx.clone());
+    }
+
+    for step in steps {
+        bundle = bundle.push_transaction(sign_wallet_transaction(wallet, step.clone()).await?);
+    }
+
+    let simulated_bundle = client
+        .1
+        .simulate_bundle(&bundle)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to simulate the probe bundle: {}", e))?;
+
+    if simulated_bundle.transactions.len() != foreign_txs.len() + steps.len() {
+        return Err(eyre::eyre!(
+            "Probe simulation returned {} of {} transactions",
+            simulated_bundle.transactions.len(),
+            foreign_txs.len() + steps.len()
+        ));
+    }
+
+    if let Some(failure) = simulated_bundle.transactions[..foreign_txs.len()]
+        .iter()
+        .find_map(|tx| probe_failure(tx, "foreign transaction"))
+    {
+        return Err(eyre::eyre!("Probe simulation failed: {}", failure));
+    }
+
+    Ok(simulated_bundle.transactions)
+}
+
+fn probe_failure(simulated_tx: &SimulatedTransaction, step: &str) -> Option<String> {
+    simulated_tx
+        .error
+        .as_ref()
+        .or(simulated_tx.revert.as_ref())
+        .map(|reason| format!("{}: {}", step, reason))
+}
+
+/// The first word of a simulated call's return value.
+pub fn decode_uint(simulated_tx: &SimulatedTransaction) -> eyre::Result<U256> {
+    let value = simulated_tx
+        .value
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("Probe simulation returned no value"))?;
+
+    if value.len() < 32 {
+        return Err(eyre::eyre!("Probe simulation returned a short value"));
+    }
+
+    Ok(U256::from_big_endian(&value[..32]))
+}
+
+/// Decodes a `getReserves` return value into `(WETH, token)` reserves.
+fn decode_reserves(
+    get_reserves: &ethabi::Function,
+    simulated_tx: &SimulatedTransaction,
+    weth: H160,
+    token_address: H160,
+) -> eyre::Result<(U256, U256)> {
+    let value = simulated_tx
+        .value
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("Probe simulation returned no reserves"))?;
+
+    let tokens = get_reserves
+        .decode_output(value)
+        .map_err(|e| eyre::eyre!("Failed to decode getReserves: {}", e))?;
+
+    let reserve = |index: usize| {
+        tokens
+            .get(index)
+            .cloned()
+            .and_then(|token| token.into_uint())
+            .ok_or_else(|| eyre::eyre!("Failed to decode getReserves"))
+    };
+
+    // token0 is always the lower address
+    if weth < token_address {
+        Ok((reserve(0)?, reserve(1)?))
+    } else {
+        Ok((reserve(1)?, reserve(0)?))
+    }
+}
+
+/// Share of `expected` that is missing from `actual`.
+fn tax(expected: U256, actual: U256) -> f64 {
+    if expected.is_zero() {
+        return 0.0;
+    }
+
+    let missing_bps = expected.saturating_sub(actual) * U256::from(10_000) / expected;
+    missing_bps.as_u64() as f64 / 10_000.0
+}
@@ -0,0 +1,266 @@
+use ethers::prelude::*;
+use ethers::types::{Filter, Transaction, H160, U256};
+use futures::{future, StreamExt};
+use std::{sync::Arc, time::Duration};
+
+use crate::core::chain::ChainConfig;
+use crate::core::contracts::{UNISWAP_V2_PAIR, UNISWAP_V2_ROUTER_02};
+use crate::core::pair::{get_amount_out, UniswapV2Pair};
+use crate::core::watcher::TX_FETCH_CONCURRENCY;
+
+/// Router functions that buy with ETH. In all of them the path is the second
+/// argument and the ETH spent is the transaction value.
+const ETH_BUY_FUNCTIONS: [&str; 3] = [
+    "swapExactETHForTokens",
+    "swapExactETHForTokensSupportingFeeOnTransferTokens",
+    "swapETHForExactTokens",
+];
+
+/// Bounds and inputs for auto-slippage.
+#[derive(Debug, Clone, Copy)]
+pub struct AutoSlippageLimits {
+    /// Lowest tolerance ever used, as a fraction.
+    pub min_slippage: f64,
+    /// Highest tolerance ever used, as a fraction.
+    pub max_slippage: f64,
+    /// How many blocks of `Sync` events feed the volatility estimate.
+    pub lookback_blocks: u64,
+    /// How long the mempool feed is watched for pending buys.
+    pub mempool_window: Duration,
+}
+
+/// Which way a trade goes through the WETH pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeSide {
+    /// WETH in, token out.
+    Buy,
+    /// Token in, WETH out.
+    Sell,
+}
+
+/// The parts auto-slippage was built from, all as fractions.
+#[derive(Debug, Clone, Copy)]
+pub struct AutoSlippage {
+    /// How far our own trade moves the price, including the swap fee. Only
+    /// reported: the quote `amountOutMin` is taken from already includes it.
+    pub price_impact: f64,
+    /// Largest deviation of the pair price from the current one over the
+    /// lookback window.
+    pub volatility: f64,
+    /// How much pending buys seen on the mempool feed would worsen our output
+    /// if they land first.
+    pub pending_impact: f64,
+    /// The clamped sum of `volatility` and `pending_impact`.
+    pub slippage: f64,
+}
+
+/// `a / b` as a float, without going through a lossy `u128` cast of either
+/// side.
+fn ratio(a: U256, b: U256) -> f64 {
+    if b.is_zero() {
+        return 0.0;
+    }
+
+    let scale = U256::exp10(18);
+    match a.checked_mul(scale) {
+        Some(scaled) => (scaled / b).low_u128() as f64 / 1e18,
+        None => (a / b).low_u128() as f64,
+    }
+}
+
+/// Derives a slippage tolerance for trading `amount_in` (wei for a buy, token
+/// units for a sell) of `token_address` from the recent `Sync` history of
+/// `chain`'s direct V2 WETH pair and the pending buys seen on `wss_url`'s
+/// mempool feed. Only valid for swaps through that pair. The tolerance
+/// covers how far the price may move before our trade lands, not our own
+/// price impact, which the quote already prices in.
+pub async fn auto_slippage(
+    amount_in: U256,
+    side: TradeSide,
+    token_address: H160,
+    limits: &AutoSlippageLimits,
+    chain: &ChainConfig,
+    wss_url: &str,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<AutoSlippage> {
+    let pair = UniswapV2Pair::load(
+        token_address,
+        &chain.v2,
+        chain.wrapped_native,
+        provider.clone(),
+    )
+    .await?;
+    let (reserve_weth, reserve_token) = (pair.reserve_weth, pair.reserve_token);
+
+    if reserve_weth.is_zero() || reserve_token.is_zero() {
+        return Err(eyre::eyre!(
+            "No liquidity for {:?}, cannot compute auto slippage",
+            token_address
+        ));
+    }
+
+    let (reserve_in, reserve_out) = match side {
+        TradeSide::Buy => (reserve_weth, reserve_token),
+        TradeSide::Sell => (reserve_token, reserve_weth),
+    };
+
+    // Price impact of our own trade against the spot price, for the log only
+    let spot_out = amount_in * reserve_out / reserve_in;
+    let quoted_out = get_amount_out(amount_in, reserve_in, reserve_out, pair.fee_bps);
+    let price_impact = 1.0 - ratio(quoted_out, spot_out);
+
+    let volatility = recent_volatility(&pair, limits.lookback_blocks, provider.clone()).await?;
+
+    // Pending buys ahead of ours push the price up, which only hurts a buy;
+    // pending sells only help it
+    let pending_buys = match side {
+        TradeSide::Buy => {
+            pending_buys_on_pair(
+                wss_url,
+                token_address,
+                chain.v2.router,
+                limits.mempool_window,
+            )
+            .await?
+        }
+        TradeSide::Sell => U256::zero(),
+    };
+
+    let pending_impact = if pending_buys.is_zero() {
+        0.0
+    } else {
+        let pending_out = get_amount_out(pending_buys, reserve_weth, reserve_token, pair.fee_bps);
+        let after_out = get_amount_out(
+            amount_in,
+            reserve_weth + pending_buys,
+            reserve_token.saturating_sub(pending_out),
+            pair.fee_bps,
+        );
+        1.0 - ratio(after_out, quoted_out)
+    };
+
+    let slippage = (volatility + pending_impact)
+        .max(limits.min_slippage)
+        .min(limits.max_slippage);
+
+    Ok(AutoSlippage {
+        price_impact,
+        volatility,
+        pending_impact,
+        slippage,
+    })
+}
+
+/// Largest relative move of the token price away from the current price,
+/// read from the pair's `Sync` events over the last `lookback_blocks` blocks.
+async fn recent_volatility(
+    pair: &UniswapV2Pair,
+    lookback_blocks: u64,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<f64> {
+    let pair_abi = ethabi::Contract::load(UNISWAP_V2_PAIR.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v2 pair contract ABI: {}", e))?;
+    let sync_topic = pair_abi
+        .event("Sync")
+        .map_err(|_| eyre::eyre!("Uniswap V2 Pair Sync event not found"))?
+        .signature();
+
+    let filter = Filter::new()
+        .address(pair.address)
+        .topic0(sync_topic)
+        .from_block(pair.synced_block.saturating_sub(lookback_blocks.into()))
+        .to_block(pair.synced_block);
+
+    let logs = provider
+        .get_logs(&filter)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get pair Sync logs: {}", e))?;
+
+    let current_price = ratio(pair.reserve_weth, pair.reserve_token);
+
+    // Replay each Sync on a copy of the pair to read the price it set
+    let mut historical = pair.clone();
+    let volatility = logs
+        .iter()
+        .map(|log| {
+            historical.apply_sync(log);
+            let price = ratio(historical.reserve_weth, historical.reserve_token);
+            (price - current_price).abs() / current_price
+        })
+        .fold(0.0, f64::max);
+
+    Ok(volatility)
+}
+
+/// Total ETH that pending `router` buys of `token_address` announced on the
+/// WebSocket mempool feed during `window` are about to spend. Buys sent
+/// before the window opened are not counted.
+async fn pending_buys_on_pair(
+    wss_url: &str,
+    token_address: H160,
+    router: H160,
+    window: Duration,
+) -> eyre::Result<U256> {
+    if window.is_zero() {
+        return Ok(U256::zero());
+    }
+
+    let router_abi = ethabi::Contract::load(UNISWAP_V2_ROUTER_02.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v2 router contract ABI: {}", e))?;
+
+    let provider = Provider::<Ws>::connect(wss_url)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to connect to WebSocket endpoint: {}", e))?;
+
+    let pending_buys = provider
+        .subscribe_pending_txs()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to subscribe to pending transactions: {}", e))?
+        .transactions_unordered(TX_FETCH_CONCURRENCY)
+        .take_until(tokio::time::sleep(window))
+        .fold(U256::zero(), |total, tx| {
+            let bought = tx
+                .ok()
+                .filter(|tx| is_eth_buy_of(&router_abi, tx, token_address, router))
+                .map_or(U256::zero(), |tx| tx.value);
+            future::ready(total + bought)
+        })
+        .await;
+
+    Ok(pending_buys)
+}
+
+/// Whether `tx` is a direct WETH -> `token_address` buy through `router`.
+fn is_eth_buy_of(
+    router_abi: &ethabi::Contract,
+    tx: &Transaction,
+    token_address: H160,
+    router: H160,
+) -> bool {
+    if tx.to != Some(router) || tx.input.len() < 4 {
+        return false;
+    }
+
+    let function = ETH_BUY_FUNCTIONS.iter().find_map(|name| {
+        router_abi
+            .function(name)
+            .ok()
+            .filter(|function| function.short_signature()[..] == tx.input[..4])
+    });
+
+    let Some(function) = function else {
+        return false;
+    };
+
+    let Ok(args) = function.decode_input(&tx.input[4..]) else {
+        return false;
+    };
+
+    // Only direct WETH -> token swaps go through our pair
+    args.get(1)
+        .cloned()
+        .and_then(|path| path.into_array())
+        .filter(|path| path.len() == 2)
+        .and_then(|path| path[1].clone().into_address())
+        .is_some_and(|last| last == token_address)
+}
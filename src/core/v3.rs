@@ -0,0 +1,325 @@
+use ethabi::Token;
+use ethers::prelude::*;
+use ethers::types::{Bytes, H160, U256};
+use ethers_core::types::Eip1559TransactionRequest;
+use futures::future::join_all;
+use std::sync::Arc;
+
+use crate::core::chain::V3Deployment;
+use crate::core::contracts::{
+    apply_slippage, deadline_timestamp, UNISWAP_V3_FACTORY_ABI, UNISWAP_V3_POOL_ABI,
+    UNISWAP_V3_QUOTER_V2_ABI, UNISWAP_V3_SWAP_ROUTER_02_ABI,
+};
+
+/// Fee tiers enabled on the Uniswap V3 factory, in hundredths of a bip.
+pub const V3_FEE_TIERS: [u32; 4] = [100, 500, 3_000, 10_000];
+
+/// How the V3 route for `tokenToBuy` is built, and on which deployment.
+#[derive(Debug, Clone)]
+pub struct V3RouteConfig {
+    /// Fee tier used for every hop; picked per hop by liquidity when unset.
+    pub fee_tier: Option<u32>,
+    /// Tokens to route through between WETH and the token, in buy order.
+    pub via: Vec<H160>,
+    pub deployment: V3Deployment,
+    /// Wrapped native token every route starts or ends with.
+    pub weth: H160,
+}
+
+/// What a V3 swap trades: the token, the slippage its minimum output allows
+/// and how its route is built.
+#[derive(Debug, Clone, Copy)]
+pub struct V3Trade<'a> {
+    pub token_address: &'a str,
+    pub slippage_percentage: f64,
+    pub route_config: &'a V3RouteConfig,
+}
+
+/// A V3 path: `tokens[i]` swaps into `tokens[i + 1]` in the pool with fee
+/// `fees[i]`.
+#[derive(Debug, Clone)]
+pub struct V3Route {
+    pub tokens: Vec<H160>,
+    pub fees: Vec<u32>,
+}
+
+impl V3Route {
+    /// Packs the route the way SwapRouter02 and QuoterV2 expect it:
+    /// `token (20 bytes) | fee (3 bytes) | token | ...`.
+    pub fn encode_path(&self) -> Bytes {
+        let mut path = Vec::with_capacity(self.tokens.len() * 23);
+
+        for (index, token) in self.tokens.iter().enumerate() {
+            path.extend_from_slice(token.as_bytes());
+            if let Some(fee) = self.fees.get(index) {
+                path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            }
+        }
+
+        Bytes::from(path)
+    }
+
+    pub fn reversed(&self) -> V3Route {
+        V3Route {
+            tokens: self.tokens.iter().rev().cloned().collect(),
+            fees: self.fees.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+/// The pool `factory` holds with the most in-range liquidity between
+/// `token_a` and `token_b`, across every fee tier.
+pub async fn select_fee_tier(
+    token_a: H160,
+    token_b: H160,
+    factory: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<u32> {
+    let factory_abi = ethabi::Contract::load(UNISWAP_V3_FACTORY_ABI.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v3 factory contract ABI: {}", e))?;
+    let pool_abi = ethabi::Contract::load(UNISWAP_V3_POOL_ABI.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v3 pool contract ABI: {}", e))?;
+    let factory = Contract::new(factory, factory_abi, provider.clone());
+
+    let liquidity_by_tier = join_all(V3_FEE_TIERS.iter().map(|fee| {
+        let factory = &factory;
+        let pool_abi = pool_abi.clone();
+        let provider = provider.clone();
+
+        async move {
+            let pool = factory
+                .method::<_, H160>("getPool", (token_a, token_b, *fee))
+                .ok()?
+                .call()
+                .await
+                .ok()?;
+
+            if pool.is_zero() {
+                return None;
+            }
+
+            let liquidity = Contract::new(pool, pool_abi, provider)
+                .method::<_, u128>("liquidity", ())
+                .ok()?
+                .call()
+                .await
+                .ok()?;
+
+            Some((*fee, liquidity))
+        }
+    }))
+    .await;
+
+    liquidity_by_tier
+        .into_iter()
+        .flatten()
+        .filter(|(_, liquidity)| *liquidity > 0)
+        .max_by_key(|(_, liquidity)| *liquidity)
+        .map(|(fee, _)| fee)
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "No Uniswap V3 pool with liquidity between {:?} and {:?}",
+                token_a,
+                token_b
+            )
+        })
+}
+
+/// Builds the WETH -> `token` route, picking each hop's fee tier by
+/// liquidity unless one is configured.
+pub async fn resolve_buy_route(
+    token: H160,
+    config: &V3RouteConfig,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<V3Route> {
+    let mut tokens = vec![config.weth];
+    tokens.extend(config.via.iter().cloned());
+    tokens.push(token);
+
+    let mut fees = Vec::with_capacity(tokens.len() - 1);
+    for hop in tokens.windows(2) {
+        let fee = match config.fee_tier {
+            Some(fee) => fee,
+            None => {
+                select_fee_tier(hop[0], hop[1], config.deployment.factory, provider.clone()).await?
+            }
+        };
+        fees.push(fee);
+    }
+
+    let route = V3Route { tokens, fees };
+    println!("Uniswap V3 route: {:?}", route);
+    Ok(route)
+}
+
+/// `quoter`'s output for swapping `amount_in` along `route`.
+pub async fn quote_exact_input(
+    route: &V3Route,
+    amount_in: U256,
+    quoter: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<U256> {
+    let quoter_abi = ethabi::Contract::load(UNISWAP_V3_QUOTER_V2_ABI.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v3 quoter contract ABI: {}", e))?;
+    let quoter = Contract::new(quoter, quoter_abi, provider);
+
+    if route.fees.len() == 1 {
+        let params = (
+            route.tokens[0],
+            route.tokens[1],
+            amount_in,
+            route.fees[0],
+            U256::zero(),
+        );
+
+        let (amount_out, _, _, _) = quoter
+            .method::<_, (U256, U256, u32, U256)>("quoteExactInputSingle", (params,))
+            .map_err(|_| eyre::eyre!("Uniswap V3 Quoter contract method not found"))?
+            .call()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to quote the V3 swap: {}", e))?;
+
+        return Ok(amount_out);
+    }
+
+    let (amount_out, _, _, _) = quoter
+        .method::<_, (U256, Vec<U256>, Vec<u32>, U256)>(
+            "quoteExactInput",
+            (route.encode_path(), amount_in),
+        )
+        .map_err(|_| eyre::eyre!("Uniswap V3 Quoter contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to quote the V3 swap: {}", e))?;
+
+    Ok(amount_out)
+}
+
+/// SwapRouter02 `multicall(deadline, data)` calldata for swapping
+/// `amount_in` along `route`. With `unwrap_to`, the router keeps the WETH
+/// and unwraps it to that address as ETH.
+pub fn swap_calldata(
+    route: &V3Route,
+    amount_in: U256,
+    amount_out_min: U256,
+    recipient: H160,
+    unwrap_to: Option<H160>,
+) -> eyre::Result<Bytes> {
+    let router_abi = ethabi::Contract::load(UNISWAP_V3_SWAP_ROUTER_02_ABI.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v3 swap router contract ABI: {}", e))?;
+    let function = |name: &str| {
+        router_abi
+            .function(name)
+            .map_err(|_| eyre::eyre!("Uniswap V3 SwapRouter02 contract method not found"))
+    };
+
+    // `address(2)` tells SwapRouter02 to hold the output itself
+    let swap_recipient = match unwrap_to {
+        Some(_) => H160::from_low_u64_be(2),
+        None => recipient,
+    };
+
+    let swap_params = if route.fees.len() == 1 {
+        function("exactInputSingle")?.encode_input(&[Token::Tuple(vec![
+            Token::Address(route.tokens[0]),
+            Token::Address(route.tokens[1]),
+            Token::Uint(U256::from(route.fees[0])),
+            Token::Address(swap_recipient),
+            Token::Uint(amount_in),
+            Token::Uint(amount_out_min),
+            Token::Uint(U256::zero()),
+        ])])
+    } else {
+        function("exactInput")?.encode_input(&[Token::Tuple(vec![
+            Token::Bytes(route.encode_path().to_vec()),
+            Token::Address(swap_recipient),
+            Token::Uint(amount_in),
+            Token::Uint(amount_out_min),
+        ])])
+    }
+    .map_err(|e| eyre::eyre!("Failed to encode the V3 swap: {}", e))?;
+
+    let mut calls = vec![Token::Bytes(swap_params)];
+
+    if let Some(unwrap_to) = unwrap_to {
+        let unwrap = function("unwrapWETH9")?
+            .encode_input(&[Token::Uint(amount_out_min), Token::Address(unwrap_to)])
+            .map_err(|e| eyre::eyre!("Failed to encode the WETH unwrap: {}", e))?;
+        calls.push(Token::Bytes(unwrap));
+    }
+
+    let multicall = function("multicall")?
+        .encode_input(&[
+            Token::Uint(U256::from(deadline_timestamp())),
+            Token::Array(calls),
+        ])
+        .map_err(|e| eyre::eyre!("Failed to encode the V3 multicall: {}", e))?;
+
+    Ok(Bytes::from(multicall))
+}
+
+/// Unsigned SwapRouter02 request buying `token` with `value` wei. Fees,
+/// nonce and gas are left to the caller.
+pub async fn v3_buy_request(
+    value: U256,
+    token: H160,
+    slippage_percentage: f64,
+    config: &V3RouteConfig,
+    wallet_address: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<Eip1559TransactionRequest> {
+    let route = resolve_buy_route(token, config, provider.clone()).await?;
+    let expected_tokens =
+        quote_exact_input(&route, value, config.deployment.quoter_v2, provider).await?;
+    let amount_out_tokens = apply_slippage(expected_tokens, slippage_percentage)?;
+
+    println!(
+        "V3 quote: {} tokens, minimum {}",
+        expected_tokens, amount_out_tokens
+    );
+
+    let transaction_data = swap_calldata(&route, value, amount_out_tokens, wallet_address, None)?;
+
+    Ok(Eip1559TransactionRequest::new()
+        .from(wallet_address)
+        .to(config.deployment.swap_router_02)
+        .value(value)
+        .data(transaction_data))
+}
+
+/// Unsigned SwapRouter02 request selling `amount_in` of `token` for ETH.
+/// The router must already be approved (or be approved earlier in the same
+/// bundle).
+pub async fn v3_sell_request(
+    amount_in: U256,
+    token: H160,
+    slippage_percentage: f64,
+    config: &V3RouteConfig,
+    wallet_address: H160,
+    provider: Arc<Provider<Http>>,
+) -> eyre::Result<Eip1559TransactionRequest> {
+    let route = resolve_buy_route(token, config, provider.clone())
+        .await?
+        .reversed();
+    let expected_ether =
+        quote_exact_input(&route, amount_in, config.deployment.quoter_v2, provider).await?;
+    let amount_out_ether = apply_slippage(expected_ether, slippage_percentage)?;
+
+    println!(
+        "V3 quote: {} wei, minimum {}",
+        expected_ether, amount_out_ether
+    );
+
+    let transaction_data = swap_calldata(
+        &route,
+        amount_in,
+        amount_out_ether,
+        wallet_address,
+        Some(wallet_address),
+    )?;
+
+    Ok(Eip1559TransactionRequest::new()
+        .from(wallet_address)
+        .to(config.deployment.swap_router_02)
+        .data(transaction_data))
+}
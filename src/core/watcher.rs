@@ -0,0 +1,302 @@
+use ethers::prelude::*;
+use ethers::types::{Bytes, H160, H256, U256, U64};
+use futures::{
+    future,
+    stream::{self, BoxStream, StreamExt},
+};
+
+use crate::core::chain::V2Deployment;
+use crate::core::contracts::{UNISWAP_V2_FACTORY_ABI, UNISWAP_V2_PAIR};
+
+/// `addLiquidityETH(address,uint256,uint256,uint256,address,uint256)`
+pub const ADD_LIQUIDITY_ETH_SELECTOR: [u8; 4] = [0xf3, 0x05, 0xd7, 0x19];
+/// `addLiquidity(address,address,uint256,uint256,uint256,uint256,address,uint256)`
+pub const ADD_LIQUIDITY_SELECTOR: [u8; 4] = [0xe8, 0xe3, 0x37, 0x00];
+
+/// Number of pending transaction bodies fetched concurrently from the node.
+pub const TX_FETCH_CONCURRENCY: usize = 64;
+
+/// First sign of liquidity for the watched token.
+#[derive(Debug, Clone)]
+pub enum LaunchEvent {
+    /// The factory emitted `PairCreated` for the token in a mined block.
+    PairCreated {
+        pair: H160,
+        block_number: Option<U64>,
+    },
+    /// An `addLiquidity`/`addLiquidityETH` call for the token is waiting in
+    /// the mempool. The raw signed transaction is kept so it can be bundled,
+    /// along with the `(WETH, token)` amounts it adds to a WETH pair.
+    PendingAddLiquidity {
+        tx_hash: H256,
+        raw_tx: Bytes,
+        added_liquidity: Option<(U256, U256)>,
+    },
+}
+
+/// Subscribes over WebSocket to `dex`'s factory `PairCreated` logs and/or
+/// the pending transaction feed, and resolves with the first event that adds
+/// liquidity for `token_address`. `weth` is the wrapped native token the
+/// added liquidity is measured against.
+pub async fn wait_for_launch(
+    wss_url: &str,
+    token_address: H160,
+    dex: &V2Deployment,
+    weth: H160,
+    watch_pair_created: bool,
+    watch_mempool: bool,
+) -> eyre::Result<LaunchEvent> {
+    if !watch_pair_created && !watch_mempool {
+        return Err(eyre::eyre!("Launch watcher has nothing to watch"));
+    }
+
+    let provider = Provider::<Ws>::connect(wss_url)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to connect to WebSocket endpoint: {}", e))?;
+
+    let factory_abi = ethabi::Contract::load(UNISWAP_V2_FACTORY_ABI.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v2 factory contract ABI: {}", e))?;
+    let pair_created = factory_abi
+        .event("PairCreated")
+        .map_err(|e| eyre::eyre!("Uniswap V2 Factory event not found: {}", e))?
+        .clone();
+
+    let pair_created_events: BoxStream<'_, LaunchEvent> = if watch_pair_created {
+        let filter = Filter::new()
+            .address(dex.factory)
+            .topic0(pair_created.signature());
+
+        provider
+            .subscribe_logs(&filter)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to subscribe to PairCreated logs: {}", e))?
+            .filter_map(move |log| {
+                future::ready(decode_pair_created(&pair_created, &log, token_address))
+            })
+            .boxed()
+    } else {
+        stream::pending().boxed()
+    };
+
+    let router = dex.router;
+    let add_liquidity_txs: BoxStream<'_, LaunchEvent> = if watch_mempool {
+        provider
+            .subscribe_pending_txs()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to subscribe to pending transactions: {}", e))?
+            .transactions_unordered(TX_FETCH_CONCURRENCY)
+            .filter_map(move |tx| {
+                future::ready(
+                    tx.ok()
+                        .and_then(|tx| decode_add_liquidity(&tx, token_address, router, weth)),
+                )
+            })
+            .boxed()
+    } else {
+        stream::pending().boxed()
+    };
+
+    println!("Watching for liquidity on {:?}...", token_address);
+
+    let event = stream::select(pair_created_events, add_liquidity_txs)
+        .next()
+        .await;
+
+    event.ok_or_else(|| eyre::eyre!("Launch watcher subscriptions ended"))
+}
+
+/// Waits until the liquidity `launch` announced is on chain: the pending
+/// liquidity add is mined, or the created pair emits its first `Sync` with
+/// non-zero reserves. A pair can be created long before anyone adds
+/// liquidity to it, and a public buy against it fails until then.
+pub async fn wait_for_liquidity(wss_url: &str, launch: &LaunchEvent) -> eyre::Result<()> {
+    let provider = Provider::<Ws>::connect(wss_url)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to connect to WebSocket endpoint: {}", e))?;
+
+    match launch {
+        LaunchEvent::PendingAddLiquidity { tx_hash, .. } => {
+            wait_for_mined(&provider, *tx_hash).await
+        }
+        LaunchEvent::PairCreated { pair, .. } => wait_for_reserves(&provider, *pair).await,
+    }
+}
+
+/// Checks for `tx_hash`'s receipt on every new block. Fails if the
+/// transaction reverts or leaves the mempool without being mined.
+async fn wait_for_mined(provider: &Provider<Ws>, tx_hash: H256) -> eyre::Result<()> {
+    let mut blocks = provider
+        .subscribe_blocks()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to subscribe to new blocks: {}", e))?;
+
+    loop {
+        let receipt = provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get the liquidity add receipt: {}", e))?;
+
+        match receipt {
+            Some(receipt) if receipt.status == Some(U64::one()) => {
+                println!(
+                    "Liquidity add {:?} mined in block {:?}",
+                    tx_hash, receipt.block_number
+                );
+                return Ok(());
+            }
+            Some(_) => return Err(eyre::eyre!("Liquidity add {:?} reverted", tx_hash)),
+            None => {}
+        }
+
+        let pending = provider
+            .get_transaction(tx_hash)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to get the liquidity add: {}", e))?;
+        if pending.is_none() {
+            return Err(eyre::eyre!(
+                "Liquidity add {:?} left the mempool without being mined",
+                tx_hash
+            ));
+        }
+
+        blocks
+            .next()
+            .await
+            .ok_or_else(|| eyre::eyre!("New block subscription ended"))?;
+    }
+}
+
+/// Resolves once `pair` holds reserves on both sides, from its current
+/// `getReserves` or the first non-zero `Sync` it emits.
+async fn wait_for_reserves(provider: &Provider<Ws>, pair: H160) -> eyre::Result<()> {
+    let pair_abi = ethabi::Contract::load(UNISWAP_V2_PAIR.as_bytes())
+        .map_err(|e| eyre::eyre!("Failed to load v2 pair contract ABI: {}", e))?;
+    let sync_topic = pair_abi
+        .event("Sync")
+        .map_err(|_| eyre::eyre!("Uniswap V2 Pair Sync event not found"))?
+        .signature();
+
+    // Subscribe before reading the reserves so a Sync in between is not missed
+    let filter = Filter::new().address(pair).topic0(sync_topic);
+    let syncs = provider
+        .subscribe_logs(&filter)
+        .await
+        .map_err(|e| eyre::eyre!("Failed to subscribe to pair Sync logs: {}", e))?;
+
+    let (reserve0, reserve1, _) = Contract::new(pair, pair_abi, provider.clone().into())
+        .method::<_, (u128, u128, u32)>("getReserves", ())
+        .map_err(|_| eyre::eyre!("Uniswap V2 Pair contract method not found"))?
+        .call()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to get pair reserves: {}", e))?;
+
+    if reserve0 > 0 && reserve1 > 0 {
+        return Ok(());
+    }
+
+    println!("Waiting for liquidity in pair {:?}...", pair);
+
+    let mut funded_syncs = syncs.filter(|log| {
+        future::ready(
+            log.data.len() >= 64
+                && !U256::from_big_endian(&log.data[0..32]).is_zero()
+                && !U256::from_big_endian(&log.data[32..64]).is_zero(),
+        )
+    });
+
+    let sync = funded_syncs
+        .next()
+        .await
+        .ok_or_else(|| eyre::eyre!("Pair Sync subscription ended"))?;
+
+    println!(
+        "Liquidity added to pair {:?} in block {:?}",
+        pair, sync.block_number
+    );
+    Ok(())
+}
+
+fn decode_pair_created(
+    pair_created: &ethabi::Event,
+    log: &Log,
+    token_address: H160,
+) -> Option<LaunchEvent> {
+    let parsed = pair_created
+        .parse_log(ethabi::RawLog {
+            topics: log.topics.clone(),
+            data: log.data.to_vec(),
+        })
+        .ok()?;
+
+    let token0 = parsed.params.first()?.value.clone().into_address()?;
+    let token1 = parsed.params.get(1)?.value.clone().into_address()?;
+
+    if token0 != token_address && token1 != token_address {
+        return None;
+    }
+
+    let pair = parsed.params.get(2)?.value.clone().into_address()?;
+
+    Some(LaunchEvent::PairCreated {
+        pair,
+        block_number: log.block_number,
+    })
+}
+
+/// Matches `router` `addLiquidityETH`/`addLiquidity` calls that touch
+/// `token_address`. The added liquidity is only known for the `weth` pair.
+pub fn decode_add_liquidity(
+    tx: &Transaction,
+    token_address: H160,
+    router: H160,
+    weth: H160,
+) -> Option<LaunchEvent> {
+    if tx.to != Some(router) || tx.input.len() < 4 {
+        return None;
+    }
+
+    let selector: [u8; 4] = tx.input[..4].try_into().ok()?;
+    let args = &tx.input[4..];
+    let word_address = |index: usize| -> Option<H160> {
+        args.get(index * 32 + 12..(index + 1) * 32)
+            .map(H160::from_slice)
+    };
+
+    let word_uint = |index: usize| -> Option<U256> {
+        args.get(index * 32..(index + 1) * 32)
+            .map(U256::from_big_endian)
+    };
+
+    let (matches, added_liquidity) = match selector {
+        ADD_LIQUIDITY_ETH_SELECTOR => (
+            word_address(0) == Some(token_address),
+            word_uint(1).map(|amount_token| (tx.value, amount_token)),
+        ),
+        ADD_LIQUIDITY_SELECTOR => {
+            let (token_a, token_b) = (word_address(0), word_address(1));
+            let added_liquidity = if token_a == Some(token_address) && token_b == Some(weth) {
+                word_uint(3).zip(word_uint(2))
+            } else if token_b == Some(token_address) && token_a == Some(weth) {
+                word_uint(2).zip(word_uint(3))
+            } else {
+                None
+            };
+
+            (
+                token_a == Some(token_address) || token_b == Some(token_address),
+                added_liquidity,
+            )
+        }
+        _ => (false, None),
+    };
+
+    if !matches {
+        return None;
+    }
+
+    Some(LaunchEvent::PendingAddLiquidity {
+        tx_hash: tx.hash,
+        raw_tx: tx.rlp(),
+        added_liquidity,
+    })
+}
@@ -8,6 +8,14 @@ pub fn wss_node_endpoint() -> Result<String, env::VarError> {
 pub fn wss_alchemy_node_endpoint() -> Result<String, env::VarError> {
     env::var("WSS_NODE_ENDPOINT")
 }
+
+pub fn keystore_password_env() -> Result<String, env::VarError> {
+    env::var("KEYSTORE_PASSWORD")
+}
+
+pub fn mnemonic_env() -> Result<String, env::VarError> {
+    env::var("WALLET_MNEMONIC")
+}
 use ethers::prelude::Provider;
 pub fn http_node_endpoint() -> eyre::Result<Provider<Http>> {
     let endpoint = env::var("HTTP_NODE_ENDPOINT")?;
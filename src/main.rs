@@ -2,18 +2,60 @@ mod connector;
 mod core;
 mod env;
 
+use clap::Parser;
 use log::info;
 
-use crate::connector::app::run_app_and_swap;
+use crate::connector::app::{
+    run_app_and_swap, run_balances, run_fund, run_quote, run_simulate, run_sweep,
+    run_validate_config, SwapAction,
+};
+use crate::connector::cli::{Cli, Command, WalletCommand};
+use crate::connector::wallets::{import_keystore, new_keystore};
 #[tokio::main]
-pub async fn main() {
+pub async fn main() -> eyre::Result<()> {
     pretty_env_logger::init();
-    info!("Starting the bot...");
-    let _bot = match run_app_and_swap().await {
-        Ok(bot) => bot,
-        Err(e) => {
-            log::error!("Error: {}", e);
-            return;
-        }
-    };
+
+    let cli = Cli::parse();
+    let args = &cli.common;
+
+    match cli.command {
+        Some(Command::Wallet(WalletCommand::New { name, dir })) => {
+            new_keystore(&name, &dir).map(|_| ())
+        }
+        Some(Command::Wallet(WalletCommand::Import { name, dir })) => {
+            import_keystore(&name, &dir).map(|_| ())
+        }
+        Some(Command::Buy) => {
+            info!("Buying from every wallet...");
+            run_app_and_swap(args, SwapAction::Buy).await
+        }
+        Some(Command::Sell) => {
+            info!("Selling from every wallet...");
+            run_app_and_swap(args, SwapAction::Sell).await
+        }
+        Some(Command::Snipe) => {
+            info!("Starting the sniper...");
+            run_app_and_swap(args, SwapAction::Snipe).await
+        }
+        Some(Command::Volume) => {
+            info!("Starting the bot...");
+            run_app_and_swap(args, SwapAction::Volume).await
+        }
+        Some(Command::Fund) => {
+            info!("Funding wallets from the treasury...");
+            run_fund(args).await
+        }
+        Some(Command::Sweep) => {
+            info!("Sweeping wallets into the treasury...");
+            run_sweep(args).await
+        }
+        Some(Command::Balances) => run_balances(args).await,
+        Some(Command::Quote) => run_quote(args).await,
+        Some(Command::Simulate) => run_simulate(args).await,
+        Some(Command::ValidateConfig) => run_validate_config(args).await,
+        None => {
+            info!("Starting the bot...");
+            run_app_and_swap(args, SwapAction::Volume).await
+        }
+    }
 }
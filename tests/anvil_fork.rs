@@ -0,0 +1,228 @@
+//! Swap paths against an anvil mainnet fork. The fork tests are ignored by
+//! default; set `FORK_URL` to an archive RPC (and optionally `FORK_BLOCK`) and
+//! run them with `cargo test -- --ignored`.
+
+mod common;
+
+use ethers::prelude::*;
+use ethers::types::{H160, U256};
+use ethers::utils::parse_ether;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use eth_volume_bot::core::chain::{GasModel, CHAINS};
+use eth_volume_bot::core::contracts::{
+    apply_slippage, load_erc20_contract, load_uniswap_v2_mempool, ConfigContractmempool, SellAmount,
+};
+use eth_volume_bot::core::gas::{GasLimitPolicy, SendSettings};
+use eth_volume_bot::core::pair::{get_amount_in, get_amount_out, TransferTax, UniswapV2Pair};
+use eth_volume_bot::core::public_txn::{uniswap_v2_sell_transaction, uniswap_v2_transaction};
+use eth_volume_bot::core::router::{find_best_route, quote_buy, V2Trade};
+
+const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+
+async fn router_amounts(
+    router: &ConfigContractmempool,
+    method: &str,
+    amount: U256,
+    path: Vec<H160>,
+) -> Vec<U256> {
+    router
+        .method::<_, Vec<U256>>(method, (amount, path))
+        .expect("router method")
+        .call()
+        .await
+        .expect("router call")
+}
+
+async fn token_balance(token: H160, wallet: &LocalWallet, provider: Arc<Provider<Http>>) -> U256 {
+    load_erc20_contract(token, wallet, provider)
+        .await
+        .expect("erc20 contract")
+        .method::<_, U256>("balanceOf", wallet.address())
+        .expect("balanceOf")
+        .call()
+        .await
+        .expect("balanceOf call")
+}
+
+#[test]
+fn apply_slippage_takes_the_fraction_off() {
+    let amount = U256::from(1_000_000u64);
+
+    assert_eq!(apply_slippage(amount, 0.0).unwrap(), amount);
+    assert_eq!(
+        apply_slippage(amount, 0.01).unwrap(),
+        U256::from(990_000u64)
+    );
+    assert_eq!(apply_slippage(amount, 0.5).unwrap(), U256::from(500_000u64));
+    assert_eq!(apply_slippage(amount, 1.0).unwrap(), U256::zero());
+    assert!(apply_slippage(amount, 1.5).is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and FORK_URL, run with --ignored"]
+async fn pair_math_matches_router() {
+    let fork = common::fork();
+    let chain = &CHAINS[&1];
+    let dai = H160::from_str(DAI).unwrap();
+
+    let router = load_uniswap_v2_mempool(chain.v2.router, &fork.wallet, fork.provider.clone())
+        .await
+        .expect("router contract");
+    let pair = UniswapV2Pair::load(dai, &chain.v2, chain.wrapped_native, fork.provider.clone())
+        .await
+        .expect("pair");
+    assert!(!pair.reserve_weth.is_zero() && !pair.reserve_token.is_zero());
+
+    let eth_in = parse_ether(1).unwrap();
+    let amounts_out = router_amounts(
+        &router,
+        "getAmountsOut",
+        eth_in,
+        vec![chain.wrapped_native, dai],
+    )
+    .await;
+    assert_eq!(
+        get_amount_out(eth_in, pair.reserve_weth, pair.reserve_token, pair.fee_bps),
+        amounts_out[1]
+    );
+
+    let dai_out = parse_ether(1_000).unwrap();
+    let amounts_in = router_amounts(
+        &router,
+        "getAmountsIn",
+        dai_out,
+        vec![chain.wrapped_native, dai],
+    )
+    .await;
+    assert_eq!(
+        get_amount_in(dai_out, pair.reserve_weth, pair.reserve_token, pair.fee_bps),
+        Some(amounts_in[0])
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and FORK_URL, run with --ignored"]
+async fn best_route_and_quote_match_router() {
+    let fork = common::fork();
+    let chain = &CHAINS[&1];
+    let dai = H160::from_str(DAI).unwrap();
+    let value = parse_ether(1).unwrap();
+
+    let router = load_uniswap_v2_mempool(chain.v2.router, &fork.wallet, fork.provider.clone())
+        .await
+        .expect("router contract");
+
+    let (path, amount_out) = find_best_route(
+        value,
+        chain.wrapped_native,
+        dai,
+        &chain.route_intermediates,
+        chain.v2.factory,
+        &router,
+        fork.provider.clone(),
+    )
+    .await
+    .expect("route");
+    assert_eq!(path.first(), Some(&chain.wrapped_native));
+    assert_eq!(path.last(), Some(&dai));
+
+    let amounts_out = router_amounts(&router, "getAmountsOut", value, path.clone()).await;
+    assert_eq!(amount_out, *amounts_out.last().unwrap());
+
+    let trade = V2Trade {
+        token_address: DAI,
+        slippage_percentage: 0.0,
+        transfer_tax: TransferTax::default(),
+        route_override: Some(&path),
+        dex: &chain.v2,
+        weth: chain.wrapped_native,
+        route_intermediates: &chain.route_intermediates,
+    };
+    let quote = quote_buy(value, U256::zero(), &trade, &router, fork.provider.clone())
+        .await
+        .expect("quote");
+    assert_eq!(quote.amount_in, value);
+    assert_eq!(quote.amount_out, amount_out);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and FORK_URL, run with --ignored"]
+async fn buy_then_sell_on_fork() {
+    let fork = common::fork();
+    let chain = &CHAINS[&1];
+    let dai = H160::from_str(DAI).unwrap();
+    let value = parse_ether(1).unwrap();
+    let slippage = 0.01;
+    let route = [chain.wrapped_native, dai];
+
+    let trade = V2Trade {
+        token_address: DAI,
+        slippage_percentage: slippage,
+        transfer_tax: TransferTax::default(),
+        route_override: Some(&route),
+        dex: &chain.v2,
+        weth: chain.wrapped_native,
+        route_intermediates: &chain.route_intermediates,
+    };
+    let send = SendSettings {
+        buy_extra_gas: U256::zero(),
+        miner_tip: U256::zero(),
+        gas_policy: &GasLimitPolicy::default(),
+        gas_model: GasModel::Eip1559,
+        dry_run: false,
+    };
+
+    let router = load_uniswap_v2_mempool(chain.v2.router, &fork.wallet, fork.provider.clone())
+        .await
+        .expect("router contract");
+    let quote = quote_buy(value, U256::zero(), &trade, &router, fork.provider.clone())
+        .await
+        .expect("quote");
+
+    let before = token_balance(dai, &fork.wallet, fork.provider.clone()).await;
+    uniswap_v2_transaction(
+        value,
+        U256::zero(),
+        &trade,
+        &fork.wallet,
+        fork.provider.clone(),
+        None,
+        &send,
+    )
+    .await
+    .expect("buy");
+    let received = token_balance(dai, &fork.wallet, fork.provider.clone()).await - before;
+
+    // Nothing else trades on the fork, so the buy fills at the quote
+    assert!(received >= apply_slippage(quote.amount_out, slippage).unwrap());
+    assert_eq!(received, quote.amount_out);
+
+    let eth_before = fork
+        .provider
+        .get_balance(fork.wallet.address(), None)
+        .await
+        .unwrap();
+    uniswap_v2_sell_transaction(
+        SellAmount::Percentage(1.0),
+        &trade,
+        &fork.wallet,
+        fork.provider.clone(),
+        None,
+        &send,
+    )
+    .await
+    .expect("sell");
+
+    assert!(token_balance(dai, &fork.wallet, fork.provider.clone())
+        .await
+        .is_zero());
+    let eth_after = fork
+        .provider
+        .get_balance(fork.wallet.address(), None)
+        .await
+        .unwrap();
+    // Roughly the ETH spent comes back, less fees, price impact and gas
+    assert!(eth_after > eth_before + value * 9 / 10);
+}
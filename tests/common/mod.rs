@@ -0,0 +1,58 @@
+// Each test binary uses a different part of the helpers
+#![allow(dead_code)]
+
+pub mod relay;
+
+use ethers::prelude::*;
+use ethers::utils::{Anvil, AnvilInstance};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A local anvil node with a funded dev wallet.
+pub struct Node {
+    /// Kills the node when dropped.
+    pub anvil: AnvilInstance,
+    pub provider: Arc<Provider<Http>>,
+    pub wallet: LocalWallet,
+}
+
+impl Node {
+    fn spawn(anvil: Anvil) -> Node {
+        let anvil = anvil.spawn();
+
+        let provider = Provider::<Http>::try_from(anvil.endpoint())
+            .expect("anvil endpoint")
+            .interval(Duration::from_millis(10));
+        let wallet = Node::dev_wallet(&anvil, 0);
+
+        Node {
+            anvil,
+            provider: Arc::new(provider),
+            wallet,
+        }
+    }
+
+    /// The `index`th funded anvil account, bound to the node's chain id.
+    pub fn dev_wallet(anvil: &AnvilInstance, index: usize) -> LocalWallet {
+        LocalWallet::from(anvil.keys()[index].clone()).with_chain_id(anvil.chain_id())
+    }
+}
+
+/// A fresh, empty dev chain.
+pub fn dev_node() -> Node {
+    Node::spawn(Anvil::new())
+}
+
+/// Forks mainnet at `FORK_BLOCK` (latest when unset) from the archive node in
+/// `FORK_URL`. Panics when `FORK_URL` is not set; the fork tests are
+/// `#[ignore]`d so they only run when asked for with `--ignored`.
+pub fn fork() -> Node {
+    let fork_url = std::env::var("FORK_URL").expect("FORK_URL must point at an archive RPC");
+
+    let mut anvil = Anvil::new().fork(fork_url);
+    if let Ok(block) = std::env::var("FORK_BLOCK") {
+        anvil = anvil.fork_block_number(block.parse::<u64>().expect("FORK_BLOCK is a number"));
+    }
+
+    Node::spawn(anvil)
+}
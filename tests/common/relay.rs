@@ -0,0 +1,593 @@
+//! A local stand-in for the Flashbots relay and its builders, so the bundle
+//! path runs without network access.
+//!
+//! One HTTP server answers on `/relay` (`eth_callBundle` and
+//! `flashbots_getBundleStats`) and on `/builder/<name>` (`eth_sendBundle`).
+//! Simulations execute the bundle on the backing dev node inside an
+//! `evm_snapshot`/`evm_revert` pair. Sent bundles are mined into their
+//! target block, once, however many builders received them.
+//!
+//! Started with `start_canned` there is no dev node. The server also
+//! answers the node calls the bundle path makes on `/node`, simulations
+//! report every transaction as a successful 21000-gas transfer, and each
+//! accepted bundle's target block is "mined" with it, so the tests need
+//! nothing but this process.
+
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::utils::{keccak256, rlp::Rlp};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use url::Url;
+
+/// The mock keeps no wall clock, so every stats timestamp is this one.
+const MOCK_TIMESTAMP: &str = "2024-01-01T00:00:00.000Z";
+
+/// Block the canned chain starts at.
+pub const CANNED_HEAD: u64 = 100;
+
+/// Gas used by every transaction of a canned simulation.
+const CANNED_GAS_USED: u64 = 21_000;
+
+/// Chain id and gas price of the canned chain.
+pub const CANNED_CHAIN_ID: u64 = 1;
+const CANNED_GAS_PRICE: u64 = 1_000_000_000;
+
+/// An `eth_sendBundle` one builder received.
+#[derive(Debug, Clone)]
+pub struct SentBundle {
+    pub builder: String,
+    /// Address recovered from `X-Flashbots-Signature`, `None` when the
+    /// header is missing or does not verify.
+    pub signer: Option<H160>,
+    pub txs: Vec<Bytes>,
+    pub block_number: U64,
+    pub accepted: bool,
+}
+
+/// An `eth_callBundle` the relay received.
+#[derive(Debug, Clone)]
+pub struct SimulationRequest {
+    pub signer: Option<H160>,
+    pub txs: Vec<Bytes>,
+}
+
+struct RelayState {
+    /// The dev node, `None` for a canned relay.
+    node: Option<Arc<Provider<Http>>>,
+    /// Canned chain head and the transactions mined in each canned block.
+    head: U64,
+    blocks: HashMap<U64, Vec<H256>>,
+    include_bundles: bool,
+    rejecting_builders: HashSet<String>,
+    sent_bundles: Vec<SentBundle>,
+    simulations: Vec<SimulationRequest>,
+    simulated: HashSet<H256>,
+    included: HashSet<H256>,
+}
+
+pub struct MockRelay {
+    base_url: Url,
+    state: Arc<Mutex<RelayState>>,
+}
+
+impl MockRelay {
+    /// Starts the relay on a free local port, backed by `node`.
+    pub async fn start(node: Arc<Provider<Http>>) -> MockRelay {
+        MockRelay::spawn(Some(node)).await
+    }
+
+    /// Starts the relay on a free local port with a canned chain instead of
+    /// a dev node, served on `node_url`.
+    pub async fn start_canned() -> MockRelay {
+        MockRelay::spawn(None).await
+    }
+
+    async fn spawn(node: Option<Arc<Provider<Http>>>) -> MockRelay {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock relay");
+        let base_url = Url::parse(&format!(
+            "http://{}",
+            listener.local_addr().expect("mock relay address")
+        ))
+        .expect("mock relay url");
+
+        let state = Arc::new(Mutex::new(RelayState {
+            node,
+            head: U64::from(CANNED_HEAD),
+            blocks: HashMap::new(),
+            include_bundles: true,
+            rejecting_builders: HashSet::new(),
+            sent_bundles: Vec::new(),
+            simulations: Vec::new(),
+            simulated: HashSet::new(),
+            included: HashSet::new(),
+        }));
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, state).await {
+                        eprintln!("Mock relay: {}", e);
+                    }
+                });
+            }
+        });
+
+        MockRelay { base_url, state }
+    }
+
+    /// The canned chain's JSON-RPC endpoint.
+    pub fn node_url(&self) -> Url {
+        self.base_url.join("node").expect("node url")
+    }
+
+    pub fn relay_url(&self) -> Url {
+        self.base_url.join("relay").expect("relay url")
+    }
+
+    pub fn builder_url(&self, name: &str) -> Url {
+        self.base_url
+            .join(&format!("builder/{}", name))
+            .expect("builder url")
+    }
+
+    /// Makes `name` answer every `eth_sendBundle` with an error.
+    pub async fn reject_builder(&self, name: &str) {
+        self.state
+            .lock()
+            .await
+            .rejecting_builders
+            .insert(name.to_string());
+    }
+
+    /// Whether sent bundles get mined. On by default.
+    pub async fn set_include_bundles(&self, include: bool) {
+        self.state.lock().await.include_bundles = include;
+    }
+
+    pub async fn sent_bundles(&self) -> Vec<SentBundle> {
+        self.state.lock().await.sent_bundles.clone()
+    }
+
+    pub async fn simulations(&self) -> Vec<SimulationRequest> {
+        self.state.lock().await.simulations.clone()
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    state: Arc<Mutex<RelayState>>,
+) -> eyre::Result<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let path = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("/")
+        .to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(eyre::eyre!("Connection closed mid-request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let body = &buffer[header_end..header_end + content_length];
+    let signer = recover_signer(
+        headers.get("x-flashbots-signature").map(String::as_str),
+        body,
+    );
+    let request: Value = serde_json::from_slice(body)?;
+
+    let response = handle_request(&path, signer, &request, &state).await;
+    let response = serde_json::to_vec(&response)?;
+
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                response.len()
+            )
+            .as_bytes(),
+        )
+        .await?;
+    stream.write_all(&response).await?;
+
+    Ok(())
+}
+
+async fn handle_request(
+    path: &str,
+    signer: Option<H160>,
+    request: &Value,
+    state: &Mutex<RelayState>,
+) -> Value {
+    let id = request["id"].clone();
+    let method = request["method"].as_str().unwrap_or_default();
+    let params = &request["params"][0];
+
+    // One request at a time, so concurrent builder submissions of the same
+    // bundle cannot both mine it
+    let mut state = state.lock().await;
+
+    let result = match (path, path.strip_prefix("/builder/"), method) {
+        (_, Some(builder), "eth_sendBundle") => state.send_bundle(builder, signer, params).await,
+        ("/relay", _, "eth_callBundle") => state.call_bundle(signer, params).await,
+        ("/relay", _, "flashbots_getBundleStats" | "flashbots_getBundleStatsV2") => {
+            state.bundle_stats(params)
+        }
+        ("/node", _, _) if state.node.is_none() => state.canned_node(method, &request["params"]),
+        _ => Err(format!("{} is not served on {}", method, path)),
+    };
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(message) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32000, "message": message },
+        }),
+    }
+}
+
+impl RelayState {
+    async fn call_bundle(&mut self, signer: Option<H160>, params: &Value) -> Result<Value, String> {
+        let txs = bundle_txs(params)?;
+        self.simulations.push(SimulationRequest {
+            signer,
+            txs: txs.clone(),
+        });
+
+        let (state_block, results) = match self.node.clone() {
+            Some(node) => simulate_on_node(&node, &txs).await?,
+            None => (self.head, canned_simulation(&txs)?),
+        };
+
+        let total_gas_used: u64 = results
+            .iter()
+            .map(|result| result["gasUsed"].as_u64().unwrap_or_default())
+            .sum();
+        let gas_fees = results.iter().fold(U256::zero(), |total, result| {
+            total
+                + U256::from_dec_str(result["gasFees"].as_str().unwrap_or("0")).unwrap_or_default()
+        });
+
+        let bundle_gas_price = match total_gas_used {
+            0 => U256::zero(),
+            gas_used => gas_fees / gas_used,
+        };
+
+        let hash = bundle_hash(&txs);
+        self.simulated.insert(hash);
+
+        Ok(json!({
+            "bundleHash": hash,
+            "bundleGasPrice": bundle_gas_price.to_string(),
+            "coinbaseDiff": gas_fees.to_string(),
+            "ethSentToCoinbase": "0",
+            "gasFees": gas_fees.to_string(),
+            "results": results,
+            "stateBlockNumber": state_block.as_u64(),
+            "totalGasUsed": total_gas_used,
+        }))
+    }
+
+    async fn send_bundle(
+        &mut self,
+        builder: &str,
+        signer: Option<H160>,
+        params: &Value,
+    ) -> Result<Value, String> {
+        let txs = bundle_txs(params)?;
+        let block_number: U64 =
+            serde_json::from_value(params["blockNumber"].clone()).map_err(|e| e.to_string())?;
+        let accepted = !self.rejecting_builders.contains(builder);
+
+        self.sent_bundles.push(SentBundle {
+            builder: builder.to_string(),
+            signer,
+            txs: txs.clone(),
+            block_number,
+            accepted,
+        });
+
+        if !accepted {
+            return Err(format!("Builder {} rejected the bundle", builder));
+        }
+
+        let hash = bundle_hash(&txs);
+        if self.include_bundles && !self.included.contains(&hash) {
+            self.include(&txs, block_number).await?;
+            self.included.insert(hash);
+        } else if self.node.is_none() {
+            // The target block still passes on a canned chain, just without
+            // the bundle
+            self.mine_canned(block_number, Vec::new());
+        }
+
+        Ok(json!({ "bundleHash": hash }))
+    }
+
+    /// Mines `txs`, and nothing else, as `block_number`. Like a real
+    /// builder, a bundle that missed its block or does not fully apply is
+    /// dropped.
+    async fn include(&mut self, txs: &[Bytes], block_number: U64) -> Result<(), String> {
+        let Some(node) = self.node.clone() else {
+            self.mine_canned(block_number, txs.iter().map(tx_hash).collect());
+            return Ok(());
+        };
+
+        let latest = node.get_block_number().await.map_err(|e| e.to_string())?;
+        if latest + 1 != block_number {
+            return Ok(());
+        }
+
+        node.request::<_, Value>("evm_setAutomine", [false])
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut applied = true;
+        for raw_tx in txs.iter() {
+            if node.send_raw_transaction(raw_tx.clone()).await.is_err() {
+                applied = false;
+                break;
+            }
+        }
+
+        let method = if applied {
+            "evm_mine"
+        } else {
+            "anvil_dropAllTransactions"
+        };
+        let mined = node.request::<_, Value>(method, ()).await;
+
+        node.request::<_, Value>("evm_setAutomine", [true])
+            .await
+            .map_err(|e| e.to_string())?;
+        mined.map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    /// Mines `block_number` on the canned chain with `txs`, if it is the
+    /// next block.
+    fn mine_canned(&mut self, block_number: U64, txs: Vec<H256>) {
+        if self.head + 1 == block_number {
+            self.head = block_number;
+            self.blocks.insert(block_number, txs);
+        }
+    }
+
+    /// The node calls the bundle path makes, answered from the canned chain.
+    fn canned_node(&self, method: &str, params: &Value) -> Result<Value, String> {
+        match method {
+            "eth_chainId" => Ok(json!(U64::from(CANNED_CHAIN_ID))),
+            "eth_blockNumber" => Ok(json!(self.head)),
+            "eth_gasPrice" => Ok(json!(U256::from(CANNED_GAS_PRICE))),
+            "eth_getBlockByNumber" => {
+                let number: U64 =
+                    serde_json::from_value(params[0].clone()).map_err(|e| e.to_string())?;
+                if number > self.head {
+                    return Ok(Value::Null);
+                }
+
+                Ok(json!({
+                    "hash": H256::from_low_u64_be(number.as_u64()),
+                    "number": number,
+                    "transactions": self.blocks.get(&number).cloned().unwrap_or_default(),
+                }))
+            }
+            _ => Err(format!("{} is not served by the canned node", method)),
+        }
+    }
+
+    fn bundle_stats(&self, params: &Value) -> Result<Value, String> {
+        let hash: H256 =
+            serde_json::from_value(params["bundleHash"].clone()).map_err(|e| e.to_string())?;
+        let is_simulated = self.simulated.contains(&hash);
+
+        let builders = |accepted_only: bool| {
+            self.sent_bundles
+                .iter()
+                .filter(|sent| bundle_hash(&sent.txs) == hash)
+                .filter(|sent| sent.accepted || !accepted_only)
+                .map(|sent| {
+                    json!({
+                        "pubkey": Bytes::from(sent.builder.as_bytes().to_vec()),
+                        "timestamp": MOCK_TIMESTAMP,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let considered = builders(true);
+        let sealed = match self.included.contains(&hash) {
+            true => considered.clone(),
+            false => Vec::new(),
+        };
+
+        Ok(json!({
+            "isHighPriority": false,
+            "isSimulated": is_simulated,
+            "isSentToMiners": !considered.is_empty(),
+            "simulatedAt": is_simulated.then_some(MOCK_TIMESTAMP),
+            "submittedAt": (!considered.is_empty()).then_some(MOCK_TIMESTAMP),
+            "sentToMinersAt": (!considered.is_empty()).then_some(MOCK_TIMESTAMP),
+            "receivedAt": (!considered.is_empty()).then_some(MOCK_TIMESTAMP),
+            "consideredByBuildersAt": considered,
+            "sealedByBuildersAt": sealed,
+        }))
+    }
+}
+
+/// Executes `txs` in order on the dev node and rolls them back.
+async fn simulate_on_node(
+    node: &Provider<Http>,
+    txs: &[Bytes],
+) -> Result<(U64, Vec<Value>), String> {
+    let state_block = node.get_block_number().await.map_err(|e| e.to_string())?;
+    let snapshot: Value = node
+        .request("evm_snapshot", ())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::new();
+    for raw_tx in txs.iter() {
+        match simulate_transaction(node, raw_tx).await {
+            Ok(result) => results.push(result),
+            Err(e) => {
+                results.push(failed_transaction(raw_tx, e));
+                break;
+            }
+        }
+    }
+
+    node.request::<_, Value>("evm_revert", [snapshot])
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok((state_block, results))
+}
+
+/// Runs one transaction on top of the ones before it: `eth_call` for the
+/// return data or revert, then a real send for the gas used.
+async fn simulate_transaction(node: &Provider<Http>, raw_tx: &Bytes) -> Result<Value, String> {
+    let (mut tx, signature) =
+        TypedTransaction::decode_signed(&Rlp::new(raw_tx)).map_err(|e| e.to_string())?;
+    let from = signature.recover(tx.sighash()).map_err(|e| e.to_string())?;
+    tx.set_from(from);
+
+    let call = node.call(&tx, None).await;
+
+    node.send_raw_transaction(raw_tx.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    let receipt = node
+        .get_transaction_receipt(tx_hash(raw_tx))
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Transaction was not mined".to_string())?;
+
+    let gas_used = receipt.gas_used.unwrap_or_default();
+    let gas_price = receipt.effective_gas_price.unwrap_or_default();
+    let (value, revert) = match call {
+        Ok(return_data) => (Some(return_data), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    Ok(json!({
+        "txHash": tx_hash(raw_tx),
+        "fromAddress": from,
+        "toAddress": tx.to_addr(),
+        "gasUsed": gas_used.as_u64(),
+        "gasPrice": gas_price.to_string(),
+        "gasFees": (gas_used * gas_price).to_string(),
+        "coinbaseDiff": (gas_used * gas_price).to_string(),
+        "ethSentToCoinbase": "0",
+        "value": value,
+        "error": revert.as_ref().map(|_| "execution reverted"),
+        "revert": revert,
+    }))
+}
+
+/// Canned results: every transaction succeeds as a plain transfer.
+fn canned_simulation(txs: &[Bytes]) -> Result<Vec<Value>, String> {
+    txs.iter()
+        .map(|raw_tx| {
+            let (tx, signature) =
+                TypedTransaction::decode_signed(&Rlp::new(raw_tx)).map_err(|e| e.to_string())?;
+            let from = signature.recover(tx.sighash()).map_err(|e| e.to_string())?;
+            let gas_fees = U256::from(CANNED_GAS_USED) * U256::from(CANNED_GAS_PRICE);
+
+            Ok(json!({
+                "txHash": tx_hash(raw_tx),
+                "fromAddress": from,
+                "toAddress": tx.to_addr(),
+                "gasUsed": CANNED_GAS_USED,
+                "gasPrice": CANNED_GAS_PRICE.to_string(),
+                "gasFees": gas_fees.to_string(),
+                "coinbaseDiff": gas_fees.to_string(),
+                "ethSentToCoinbase": "0",
+                "value": "0x",
+                "error": null,
+                "revert": null,
+            }))
+        })
+        .collect()
+}
+
+/// Result entry for a transaction the dev node would not even accept.
+fn failed_transaction(raw_tx: &Bytes, error: String) -> Value {
+    json!({
+        "txHash": tx_hash(raw_tx),
+        "fromAddress": H160::zero(),
+        "toAddress": null,
+        "gasUsed": 0,
+        "gasPrice": "0",
+        "gasFees": "0",
+        "coinbaseDiff": "0",
+        "ethSentToCoinbase": "0",
+        "value": null,
+        "error": error,
+        "revert": null,
+    })
+}
+
+fn bundle_txs(params: &Value) -> Result<Vec<Bytes>, String> {
+    serde_json::from_value(params["txs"].clone()).map_err(|e| e.to_string())
+}
+
+fn tx_hash(raw_tx: &Bytes) -> H256 {
+    H256::from(keccak256(raw_tx))
+}
+
+/// Flashbots-style bundle hash: the hash of every transaction hash in order.
+fn bundle_hash(txs: &[Bytes]) -> H256 {
+    let hashes = txs
+        .iter()
+        .flat_map(|raw_tx| tx_hash(raw_tx).to_fixed_bytes())
+        .collect::<Vec<u8>>();
+    H256::from(keccak256(hashes))
+}
+
+/// Verifies `X-Flashbots-Signature: <address>:<signature>`, which signs the
+/// hex string of the body's keccak hash.
+fn recover_signer(header: Option<&str>, body: &[u8]) -> Option<H160> {
+    let (address, signature) = header?.split_once(':')?;
+    let address = H160::from_str(address).ok()?;
+    let signature = Signature::from_str(signature).ok()?;
+
+    let message = format!("0x{:x}", H256::from(keccak256(body)));
+    (signature.recover(message).ok()? == address).then_some(address)
+}
@@ -0,0 +1,573 @@
+//! The Flashbots bundle path against the local mock relay. The `canned_`
+//! tests run against the relay's canned chain and need nothing else. The
+//! rest run on an anvil dev node, need `anvil` on the `PATH` but no network
+//! access, and are ignored by default; run them with
+//! `cargo test -- --ignored`.
+
+mod common;
+
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::utils::{parse_ether, parse_units, rlp::Rlp};
+use ethers_core::types::Eip1559TransactionRequest;
+use ethers_flashbots::BundleRequest;
+use spinners::{Spinner, Spinners};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use common::relay::{MockRelay, CANNED_CHAIN_ID, CANNED_HEAD};
+use eth_volume_bot::core::chain::{GasModel, CHAINS};
+use eth_volume_bot::core::contracts::{
+    load_erc20_contract, load_flashbots_client_middleware, BuilderConfig, BundleClient, RelayConfig,
+};
+use eth_volume_bot::core::gas::{GasLimitPolicy, SendSettings};
+use eth_volume_bot::core::pair::TransferTax;
+use eth_volume_bot::core::private_txn::{
+    sign_wallet_transaction, submit_bundle_until_included, uniswap_v2_bundler, BundleTarget,
+    BundleTransactionSource,
+};
+use eth_volume_bot::core::router::V2Trade;
+
+/// Contract creation code whose runtime code is `PUSH1 0 PUSH1 0 REVERT`.
+const ALWAYS_REVERTS: &str = "0x6460006000fd6000526005601bf3";
+
+const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+
+async fn bundle_client(
+    relay: &MockRelay,
+    builders: Vec<BuilderConfig>,
+    bundle_signer: &LocalWallet,
+    provider: Arc<Provider<Http>>,
+) -> BundleClient {
+    let relay_config = RelayConfig {
+        relay_url: relay.relay_url(),
+        builders,
+        bundle_signer: Some(bundle_signer.clone()),
+    };
+
+    load_flashbots_client_middleware(bundle_signer, provider, &relay_config)
+        .await
+        .expect("bundle client")
+}
+
+fn builder(relay: &MockRelay, name: &str, signer: Option<LocalWallet>) -> BuilderConfig {
+    BuilderConfig {
+        url: relay.builder_url(name),
+        signer,
+    }
+}
+
+/// A 1 ETH transfer from `wallet` to `to`, with its nonce set.
+async fn transfer(
+    wallet: &LocalWallet,
+    to: H160,
+    nonce_offset: u64,
+    provider: &Provider<Http>,
+) -> Eip1559TransactionRequest {
+    let nonce = provider
+        .get_transaction_count(wallet.address(), None)
+        .await
+        .unwrap();
+
+    Eip1559TransactionRequest::new()
+        .to(to)
+        .value(parse_ether(1).unwrap())
+        .gas(21_000)
+        .max_fee_per_gas(parse_units(10, "gwei").unwrap())
+        .max_priority_fee_per_gas(parse_units(1, "gwei").unwrap())
+        .nonce(nonce + nonce_offset)
+}
+
+async fn deploy(code: &str, wallet: &LocalWallet, provider: Arc<Provider<Http>>) -> H160 {
+    let request = Eip1559TransactionRequest::new()
+        .data(Bytes::from_str(code).unwrap())
+        .gas(100_000);
+
+    SignerMiddleware::new(provider, wallet.clone())
+        .send_transaction(request, None)
+        .await
+        .expect("deploy")
+        .await
+        .expect("deploy receipt")
+        .and_then(|receipt| receipt.contract_address)
+        .expect("contract address")
+}
+
+/// No extra fees on top of the dev node's own.
+fn send_settings(gas_policy: &GasLimitPolicy) -> SendSettings<'_> {
+    SendSettings {
+        buy_extra_gas: U256::zero(),
+        miner_tip: U256::zero(),
+        gas_policy,
+        gas_model: GasModel::Eip1559,
+        dry_run: false,
+    }
+}
+
+/// A fresh key for the canned chain, which checks no balances.
+fn canned_wallet() -> LocalWallet {
+    LocalWallet::new(&mut rand::thread_rng()).with_chain_id(CANNED_CHAIN_ID)
+}
+
+/// A 1 ETH transfer from a fresh wallet, as the canned chain simulates it.
+fn canned_transfer() -> BundleTransactionSource {
+    let request = Eip1559TransactionRequest::new()
+        .to(H160::repeat_byte(1))
+        .value(parse_ether(1).unwrap())
+        .gas(50_000)
+        .nonce(0);
+
+    BundleTransactionSource::Own {
+        wallet: canned_wallet(),
+        request: Box::new(request),
+    }
+}
+
+fn gas_limit(raw_tx: &Bytes) -> U256 {
+    let (tx, _) = TypedTransaction::decode_signed(&Rlp::new(raw_tx)).expect("signed transaction");
+    *tx.gas().expect("gas limit")
+}
+
+#[tokio::test]
+async fn canned_bundle_fans_out_to_every_builder() {
+    let relay = MockRelay::start_canned().await;
+    let provider = Arc::new(Provider::<Http>::try_from(relay.node_url().as_str()).unwrap());
+    let bundle_signer = canned_wallet();
+    let beta_signer = canned_wallet();
+    relay.reject_builder("gamma").await;
+
+    let client = bundle_client(
+        &relay,
+        vec![
+            builder(&relay, "alpha", None),
+            builder(&relay, "beta", Some(beta_signer.clone())),
+            builder(&relay, "gamma", None),
+        ],
+        &bundle_signer,
+        provider.clone(),
+    )
+    .await;
+
+    let gas_policy = GasLimitPolicy::default();
+    let send = SendSettings {
+        gas_model: GasModel::Legacy,
+        ..send_settings(&gas_policy)
+    };
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Sending bundle...".into());
+    submit_bundle_until_included(
+        BundleTarget {
+            client: &client,
+            block_budget: 1,
+        },
+        &provider,
+        Some(&gas_policy),
+        vec![canned_transfer()],
+        &send,
+        &mut sp,
+    )
+    .await
+    .expect("bundle included");
+
+    assert_eq!(relay.simulations().await.len(), 1);
+
+    let mut sent = relay.sent_bundles().await;
+    sent.sort_by(|a, b| a.builder.cmp(&b.builder));
+    let builders = sent
+        .iter()
+        .map(|bundle| (bundle.builder.as_str(), bundle.accepted))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        builders,
+        vec![("alpha", true), ("beta", true), ("gamma", false)]
+    );
+
+    assert_eq!(sent[0].signer, Some(bundle_signer.address()));
+    assert_eq!(sent[1].signer, Some(beta_signer.address()));
+    assert_eq!(sent[2].signer, Some(bundle_signer.address()));
+
+    for bundle in sent.iter() {
+        assert_eq!(bundle.txs, sent[0].txs);
+        assert_eq!(bundle.block_number, U64::from(CANNED_HEAD + 1));
+    }
+
+    // The canned simulation reports 21000 gas, which the limit is tightened to
+    assert_eq!(
+        gas_limit(&sent[0].txs[0]),
+        gas_policy.apply(U256::from(21_000))
+    );
+}
+
+#[tokio::test]
+async fn canned_missed_bundles_are_resubmitted_until_the_budget_runs_out() {
+    let relay = MockRelay::start_canned().await;
+    let provider = Arc::new(Provider::<Http>::try_from(relay.node_url().as_str()).unwrap());
+    relay.set_include_bundles(false).await;
+
+    let client = bundle_client(
+        &relay,
+        vec![builder(&relay, "alpha", None)],
+        &canned_wallet(),
+        provider.clone(),
+    )
+    .await;
+
+    let gas_policy = GasLimitPolicy::default();
+    let send = SendSettings {
+        gas_model: GasModel::Legacy,
+        ..send_settings(&gas_policy)
+    };
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Sending bundle...".into());
+    let result = submit_bundle_until_included(
+        BundleTarget {
+            client: &client,
+            block_budget: 2,
+        },
+        &provider,
+        None,
+        vec![canned_transfer()],
+        &send,
+        &mut sp,
+    )
+    .await;
+
+    let error = result.expect_err("never included").to_string();
+    assert!(error.contains("not included within 2 blocks"), "{}", error);
+
+    let sent = relay.sent_bundles().await;
+    let blocks = sent
+        .iter()
+        .map(|bundle| bundle.block_number.as_u64())
+        .collect::<Vec<_>>();
+    assert_eq!(blocks, vec![CANNED_HEAD + 1, CANNED_HEAD + 2]);
+    assert_eq!(relay.simulations().await.len(), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil on the PATH, run with --ignored"]
+async fn simulation_runs_the_bundle_without_changing_state() {
+    let node = common::dev_node();
+    let relay = MockRelay::start(node.provider.clone()).await;
+    let bundle_signer = common::Node::dev_wallet(&node.anvil, 9);
+    let client = bundle_client(&relay, Vec::new(), &bundle_signer, node.provider.clone()).await;
+
+    let recipient = common::Node::dev_wallet(&node.anvil, 1).address();
+    let balance_before = node.provider.get_balance(recipient, None).await.unwrap();
+    let block_number = node.provider.get_block_number().await.unwrap();
+
+    let mut bundle = BundleRequest::new()
+        .set_block(block_number + 1)
+        .set_simulation_block(block_number)
+        .set_simulation_timestamp(0);
+    for nonce_offset in 0..2 {
+        let request = transfer(&node.wallet, recipient, nonce_offset, &node.provider).await;
+        bundle = bundle.push_transaction(
+            sign_wallet_transaction(&node.wallet, request)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let simulated = client.1.simulate_bundle(&bundle).await.expect("simulation");
+
+    assert_eq!(simulated.transactions.len(), 2);
+    for simulated_tx in simulated.transactions.iter() {
+        assert_eq!(simulated_tx.gas_used, U256::from(21_000));
+        assert!(simulated_tx.error.is_none() && simulated_tx.revert.is_none());
+    }
+    assert_eq!(simulated.gas_used, U256::from(42_000));
+
+    // Simulations run inside a snapshot
+    assert_eq!(
+        node.provider.get_balance(recipient, None).await.unwrap(),
+        balance_before
+    );
+    assert_eq!(
+        node.provider.get_block_number().await.unwrap(),
+        block_number
+    );
+
+    let simulations = relay.simulations().await;
+    assert_eq!(simulations.len(), 1);
+    assert_eq!(simulations[0].signer, Some(bundle_signer.address()));
+}
+
+#[tokio::test]
+#[ignore = "needs anvil on the PATH, run with --ignored"]
+async fn reverting_bundle_is_never_sent() {
+    let node = common::dev_node();
+    let relay = MockRelay::start(node.provider.clone()).await;
+    let bundle_signer = common::Node::dev_wallet(&node.anvil, 9);
+    let client = bundle_client(
+        &relay,
+        vec![builder(&relay, "alpha", None)],
+        &bundle_signer,
+        node.provider.clone(),
+    )
+    .await;
+
+    let reverter = deploy(ALWAYS_REVERTS, &node.wallet, node.provider.clone()).await;
+    let request = transfer(&node.wallet, reverter, 0, &node.provider)
+        .await
+        .value(U256::zero())
+        .gas(50_000);
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Sending bundle...".into());
+    let result = submit_bundle_until_included(
+        BundleTarget {
+            client: &client,
+            block_budget: 1,
+        },
+        &node.provider,
+        None,
+        vec![BundleTransactionSource::Own {
+            wallet: node.wallet.clone(),
+            request: Box::new(request),
+        }],
+        &send_settings(&GasLimitPolicy::default()),
+        &mut sp,
+    )
+    .await;
+
+    let error = result.expect_err("reverting bundle").to_string();
+    assert!(error.contains("Bundle simulation failed"), "{}", error);
+    assert_eq!(relay.simulations().await.len(), 1);
+    assert!(relay.sent_bundles().await.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs anvil on the PATH, run with --ignored"]
+async fn bundle_fans_out_to_every_builder() {
+    let node = common::dev_node();
+    let relay = MockRelay::start(node.provider.clone()).await;
+    let bundle_signer = common::Node::dev_wallet(&node.anvil, 9);
+    let beta_signer = common::Node::dev_wallet(&node.anvil, 8);
+    relay.reject_builder("gamma").await;
+
+    let client = bundle_client(
+        &relay,
+        vec![
+            builder(&relay, "alpha", None),
+            builder(&relay, "beta", Some(beta_signer.clone())),
+            builder(&relay, "gamma", None),
+        ],
+        &bundle_signer,
+        node.provider.clone(),
+    )
+    .await;
+
+    let recipient = common::Node::dev_wallet(&node.anvil, 1).address();
+    let balance_before = node.provider.get_balance(recipient, None).await.unwrap();
+    let block_number = node.provider.get_block_number().await.unwrap();
+    let gas_policy = GasLimitPolicy::default();
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Sending bundle...".into());
+    submit_bundle_until_included(
+        BundleTarget {
+            client: &client,
+            block_budget: 2,
+        },
+        &node.provider,
+        Some(&gas_policy),
+        vec![BundleTransactionSource::Own {
+            wallet: node.wallet.clone(),
+            request: Box::new(transfer(&node.wallet, recipient, 0, &node.provider).await),
+        }],
+        &send_settings(&gas_policy),
+        &mut sp,
+    )
+    .await
+    .expect("bundle included");
+
+    let mut sent = relay.sent_bundles().await;
+    sent.sort_by(|a, b| a.builder.cmp(&b.builder));
+    let builders = sent
+        .iter()
+        .map(|bundle| (bundle.builder.as_str(), bundle.accepted))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        builders,
+        vec![("alpha", true), ("beta", true), ("gamma", false)]
+    );
+
+    // Each builder gets the shared signer unless it has its own
+    assert_eq!(sent[0].signer, Some(bundle_signer.address()));
+    assert_eq!(sent[1].signer, Some(beta_signer.address()));
+    assert_eq!(sent[2].signer, Some(bundle_signer.address()));
+
+    for bundle in sent.iter() {
+        assert_eq!(bundle.txs, sent[0].txs);
+        assert_eq!(bundle.block_number, block_number + 1);
+    }
+
+    // The gas limit is tightened from the simulation before sending
+    assert_eq!(
+        gas_limit(&sent[0].txs[0]),
+        gas_policy.apply(U256::from(21_000))
+    );
+
+    assert_eq!(
+        node.provider.get_balance(recipient, None).await.unwrap(),
+        balance_before + parse_ether(1).unwrap()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs anvil on the PATH, run with --ignored"]
+async fn missed_bundles_are_resubmitted_until_the_budget_runs_out() {
+    let node = common::dev_node();
+    let relay = MockRelay::start(node.provider.clone()).await;
+    let bundle_signer = common::Node::dev_wallet(&node.anvil, 9);
+    relay.set_include_bundles(false).await;
+
+    let client = bundle_client(
+        &relay,
+        vec![builder(&relay, "alpha", None)],
+        &bundle_signer,
+        node.provider.clone(),
+    )
+    .await;
+
+    let recipient = common::Node::dev_wallet(&node.anvil, 1).address();
+    let request = transfer(&node.wallet, recipient, 0, &node.provider).await;
+
+    // Nothing mines blocks on its own, so mine one per attempt
+    let miner = node.provider.clone();
+    let mining = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            let _ = miner.request::<_, serde_json::Value>("evm_mine", ()).await;
+        }
+    });
+
+    let mut sp = Spinner::new(Spinners::Dots9, "Sending bundle...".into());
+    let result = submit_bundle_until_included(
+        BundleTarget {
+            client: &client,
+            block_budget: 3,
+        },
+        &node.provider,
+        None,
+        vec![BundleTransactionSource::Own {
+            wallet: node.wallet.clone(),
+            request: Box::new(request),
+        }],
+        &send_settings(&GasLimitPolicy::default()),
+        &mut sp,
+    )
+    .await;
+    mining.abort();
+
+    let error = result.expect_err("never included").to_string();
+    assert!(error.contains("not included within 3 blocks"), "{}", error);
+
+    let sent = relay.sent_bundles().await;
+    assert_eq!(sent.len(), 3);
+    assert!(sent
+        .windows(2)
+        .all(|pair| pair[1].block_number > pair[0].block_number));
+}
+
+#[tokio::test]
+#[ignore = "needs anvil on the PATH, run with --ignored"]
+async fn bundle_stats_track_simulation_and_builders() {
+    let node = common::dev_node();
+    let relay = MockRelay::start(node.provider.clone()).await;
+    let bundle_signer = common::Node::dev_wallet(&node.anvil, 9);
+    let client = bundle_client(
+        &relay,
+        vec![builder(&relay, "alpha", None)],
+        &bundle_signer,
+        node.provider.clone(),
+    )
+    .await;
+
+    let recipient = common::Node::dev_wallet(&node.anvil, 1).address();
+    let block_number = node.provider.get_block_number().await.unwrap();
+    let request = transfer(&node.wallet, recipient, 0, &node.provider).await;
+    let bundle = BundleRequest::new()
+        .set_block(block_number + 1)
+        .set_simulation_block(block_number)
+        .set_simulation_timestamp(0)
+        .push_transaction(
+            sign_wallet_transaction(&node.wallet, request)
+                .await
+                .unwrap(),
+        );
+
+    let simulated = client.1.simulate_bundle(&bundle).await.expect("simulation");
+    let responses = client.0[0].1.send_bundle(&bundle).await.expect("send");
+    assert!(responses.iter().all(|response| response.is_ok()));
+
+    let stats = client
+        .1
+        .get_bundle_stats(simulated.hash, block_number + 1)
+        .await
+        .expect("bundle stats");
+    assert!(stats.is_simulated);
+    assert_eq!(stats.considered_by_builders_at.len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and FORK_URL, run with --ignored"]
+async fn uniswap_v2_bundler_lands_through_the_mock_relay() {
+    let node = common::fork();
+    let relay = MockRelay::start(node.provider.clone()).await;
+    let bundle_signer = common::Node::dev_wallet(&node.anvil, 9);
+    let client = bundle_client(
+        &relay,
+        vec![
+            builder(&relay, "alpha", None),
+            builder(&relay, "beta", None),
+        ],
+        &bundle_signer,
+        node.provider.clone(),
+    )
+    .await;
+
+    let chain = &CHAINS[&1];
+    let dai = H160::from_str(DAI).unwrap();
+    let route = [chain.wrapped_native, dai];
+
+    let trade = V2Trade {
+        token_address: DAI,
+        slippage_percentage: 0.01,
+        transfer_tax: TransferTax::default(),
+        route_override: Some(&route),
+        dex: &chain.v2,
+        weth: chain.wrapped_native,
+        route_intermediates: &chain.route_intermediates,
+    };
+    let gas_policy = GasLimitPolicy::default();
+    let send = send_settings(&gas_policy);
+
+    uniswap_v2_bundler(
+        parse_ether(1).unwrap(),
+        U256::zero(),
+        &trade,
+        &node.wallet,
+        node.provider.clone(),
+        &send,
+        BundleTarget {
+            client: &client,
+            block_budget: 2,
+        },
+    )
+    .await
+    .expect("bundle included");
+
+    let sent = relay.sent_bundles().await;
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|bundle| bundle.txs.len() == 1));
+
+    let balance = load_erc20_contract(dai, &node.wallet, node.provider.clone())
+        .await
+        .expect("erc20 contract")
+        .method::<_, U256>("balanceOf", node.wallet.address())
+        .expect("balanceOf")
+        .call()
+        .await
+        .expect("balanceOf call");
+    assert!(!balance.is_zero());
+}